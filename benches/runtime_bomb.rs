@@ -0,0 +1,37 @@
+//! Measures the cost of creating and dropping a `RuntimeBomb` with
+//! runtime checks off, against a real `DropBomb`.
+//!
+//! Run with `cargo bench`.
+extern crate drop_bomb;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use drop_bomb::{DropBomb, RuntimeBomb};
+
+const ITERATIONS: u32 = 10_000_000;
+
+fn bench(name: &str, f: impl Fn()) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<24} {:>8.2} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    drop_bomb::set_runtime_checks(false);
+    bench("RuntimeBomb (off)", || {
+        drop(black_box(RuntimeBomb::new("Kaboom")));
+    });
+    bench("DropBomb (defused)", || {
+        let mut bomb = black_box(DropBomb::new("Kaboom"));
+        bomb.defuse();
+        drop(bomb);
+    });
+}
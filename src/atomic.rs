@@ -0,0 +1,173 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use global;
+
+/// A `DropBomb` which can be defused through a shared reference.
+///
+/// Useful when the bomb is shared between threads, for example via an
+/// `Arc`, and any one of the owners may fulfil the obligation. There is
+/// a single flag for all the clones of the `Arc`: defusing through any
+/// of them is enough, and only the drop of the last clone can detonate.
+/// `Arc::strong_count` tells how many owners are left.
+///
+/// It is also the bomb to use when only `&self` is at hand, for example
+/// in a `&self` method of the owning struct. (`DropBomb` keeps taking
+/// `&mut self`: a `Cell` flag would make it `!Sync`. With `&mut self`,
+/// borrowing the bomb field directly, as `self.bomb.defuse()`, leaves
+/// the sibling fields free.)
+#[must_use]
+#[clippy::has_significant_drop]
+pub struct AtomicDropBomb {
+    msg: Cow<'static, str>,
+    defused: AtomicBool,
+}
+
+impl AtomicDropBomb {
+    pub fn new(msg: impl Into<Cow<'static, str>>) -> AtomicDropBomb {
+        global::record_construction();
+        AtomicDropBomb {
+            msg: msg.into(),
+            defused: AtomicBool::new(false),
+        }
+    }
+    pub fn defuse(&self) {
+        self.set_defused(true)
+    }
+    pub fn set_defused(&self, defused: bool) {
+        self.defused.store(defused, Ordering::Release)
+    }
+    pub fn is_defused(&self) -> bool {
+        self.defused.load(Ordering::Acquire)
+    }
+    /// Whether dropping the bomb now would detonate it: the bomb is
+    /// armed, and detonation is not globally disabled.
+    pub fn is_armed(&self) -> bool {
+        self.is_really_armed() && global::enabled()
+    }
+    /// Whether the bomb itself is armed, regardless of the global switch.
+    pub fn is_really_armed(&self) -> bool {
+        !self.is_defused()
+    }
+    /// Defuses a shared bomb on behalf of all its owners.
+    pub fn defuse_shared(this: &Arc<AtomicDropBomb>) {
+        this.defuse()
+    }
+    /// Atomically sets the defused state, returning the previous one.
+    ///
+    /// `swap_defused(true)` returns `false` for exactly one of several
+    /// racing callers, which makes it suitable for "whoever gets here
+    /// first cleans up" protocols.
+    pub fn swap_defused(&self, defused: bool) -> bool {
+        self.defused.swap(defused, Ordering::AcqRel)
+    }
+}
+
+impl fmt::Debug for AtomicDropBomb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AtomicDropBomb")
+            .field("message", &self.msg)
+            .field("defused", &self.is_defused())
+            .finish()
+    }
+}
+
+impl Drop for AtomicDropBomb {
+    fn drop(&mut self) {
+        global::record_drop();
+        if !*self.defused.get_mut() && global::enabled() && !::std::thread::panicking() {
+            global::record_detonation(&self.msg);
+            global::panic_detonation(&self.msg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    #[should_panic(expected = "Kaboom")]
+    fn armed_bomb_bombs() {
+        let _b = AtomicDropBomb::new("Kaboom");
+    }
+
+    #[test]
+    fn defused_bomb_is_safe() {
+        let b = AtomicDropBomb::new("Kaboom");
+        assert!(!b.is_defused());
+        assert!(!b.swap_defused(true));
+        assert!(b.swap_defused(true));
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn defuse_shared_through_any_clone() {
+        let b = Arc::new(AtomicDropBomb::new("Kaboom"));
+        assert!(b.is_armed());
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let b = Arc::clone(&b);
+                thread::spawn(move || {
+                    if i == 3 {
+                        AtomicDropBomb::defuse_shared(&b);
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert_eq!(Arc::strong_count(&b), 1);
+        assert!(!b.is_armed());
+    }
+
+    #[test]
+    fn defuse_through_shared_reference() {
+        struct Conn {
+            buf: Vec<u8>,
+            bomb: AtomicDropBomb,
+        }
+        impl Conn {
+            fn close(&self) -> usize {
+                self.bomb.defuse();
+                self.buf.len()
+            }
+        }
+
+        let mut conn = Conn {
+            buf: Vec::new(),
+            bomb: AtomicDropBomb::new("Kaboom"),
+        };
+        {
+            let buf = &mut conn.buf;
+            let bomb = &conn.bomb;
+            buf.push(92);
+            assert!(bomb.is_armed());
+        }
+        assert_eq!(conn.close(), 1);
+        assert!(conn.bomb.is_defused());
+    }
+
+    #[test]
+    fn swap_defused_from_many_threads() {
+        let b = Arc::new(AtomicDropBomb::new("Kaboom"));
+        for _ in 0..100 {
+            b.set_defused(false);
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    let b = Arc::clone(&b);
+                    thread::spawn(move || !b.swap_defused(true))
+                })
+                .collect();
+            let winners = handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .filter(|&won| won)
+                .count();
+            assert_eq!(winners, 1);
+            assert!(b.is_defused());
+        }
+    }
+}
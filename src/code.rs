@@ -0,0 +1,82 @@
+use std::fmt;
+
+use global;
+
+/// A bomb identified by a `u32` code instead of a message.
+///
+/// For size-constrained builds: a `CodeBomb` is eight bytes and never
+/// allocates until it detonates, with "drop bomb code N".
+#[must_use]
+#[clippy::has_significant_drop]
+pub struct CodeBomb {
+    code: u32,
+    defused: bool,
+}
+
+impl CodeBomb {
+    pub fn new(code: u32) -> CodeBomb {
+        global::record_construction();
+        CodeBomb {
+            code,
+            defused: false,
+        }
+    }
+    pub fn defuse(&mut self) {
+        self.set_defused(true)
+    }
+    pub fn set_defused(&mut self, defused: bool) {
+        self.defused = defused
+    }
+    pub fn is_defused(&self) -> bool {
+        self.defused
+    }
+    pub fn code(&self) -> u32 {
+        self.code
+    }
+}
+
+impl fmt::Debug for CodeBomb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CodeBomb")
+            .field("code", &self.code)
+            .field("defused", &self.defused)
+            .finish()
+    }
+}
+
+impl Drop for CodeBomb {
+    fn drop(&mut self) {
+        global::record_drop();
+        if !self.defused && global::enabled() && !::std::thread::panicking() {
+            let msg = format!("drop bomb code {}", self.code);
+            global::record_detonation(&msg);
+            global::panic_detonation(&msg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    #[should_panic(expected = "drop bomb code 92")]
+    fn armed_bomb_bombs() {
+        let _b = CodeBomb::new(92);
+    }
+
+    #[test]
+    fn defused_bomb_is_safe() {
+        let mut b = CodeBomb::new(92);
+        assert!(!b.is_defused());
+        b.defuse();
+        assert!(b.is_defused());
+        assert_eq!(b.code(), 92);
+    }
+
+    #[test]
+    fn code_bomb_is_small() {
+        assert!(mem::size_of::<CodeBomb>() <= 8);
+    }
+}
@@ -0,0 +1,367 @@
+//! Process-wide (and some thread-local) state.
+//!
+//! Everything here is lazily initialized and built only on std atomics
+//! and locks, so that drop_bomb stays free of dependencies.
+use std::cell::{Cell, RefCell};
+#[cfg(any(feature = "metrics", feature = "debug_registry"))]
+use std::collections::HashMap;
+#[cfg(feature = "intern")]
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+#[cfg(feature = "debug_registry")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::Mutex;
+#[cfg(any(feature = "metrics", feature = "debug_registry", feature = "intern"))]
+use std::sync::OnceLock;
+
+use DetonationAction;
+
+const UNKNOWN: u8 = 0;
+const ENABLED: u8 = 1;
+const DISABLED: u8 = 2;
+
+static ENABLED_STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Whether armed bombs detonate at all.
+///
+/// This is on unless the process was started with `DROP_BOMB_DISABLE=1`.
+/// The variable is read the first time this is called, and the result
+/// is cached.
+pub(crate) fn enabled() -> bool {
+    // Acquire pairs with a `Release` store from `set_enabled_ordered`.
+    match ENABLED_STATE.load(Ordering::Acquire) {
+        ENABLED => true,
+        DISABLED => false,
+        _ => {
+            let disabled = env::var_os("DROP_BOMB_DISABLE").is_some_and(|it| it == "1");
+            let state = if disabled { DISABLED } else { ENABLED };
+            match ENABLED_STATE.compare_exchange(
+                UNKNOWN,
+                state,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => state == ENABLED,
+                Err(current) => current == ENABLED,
+            }
+        }
+    }
+}
+
+pub(crate) fn set_enabled(enabled: bool, order: Ordering) {
+    let state = if enabled { ENABLED } else { DISABLED };
+    ENABLED_STATE.store(state, order)
+}
+
+static RUNTIME_CHECKS: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Whether new `RuntimeBomb`s are real. This is off unless the process
+/// was started with `DROP_BOMB_RUNTIME_CHECKS=1`; the variable is read
+/// once and cached, like `DROP_BOMB_DISABLE`.
+pub(crate) fn runtime_checks() -> bool {
+    match RUNTIME_CHECKS.load(Ordering::Relaxed) {
+        ENABLED => true,
+        DISABLED => false,
+        _ => {
+            let checks = env::var_os("DROP_BOMB_RUNTIME_CHECKS").is_some_and(|it| it == "1");
+            let state = if checks { ENABLED } else { DISABLED };
+            match RUNTIME_CHECKS.compare_exchange(
+                UNKNOWN,
+                state,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => checks,
+                Err(current) => current == ENABLED,
+            }
+        }
+    }
+}
+
+pub(crate) fn set_runtime_checks(checks: bool) {
+    let state = if checks { ENABLED } else { DISABLED };
+    RUNTIME_CHECKS.store(state, Ordering::Relaxed)
+}
+
+static DEFAULT_ACTION: AtomicU8 = AtomicU8::new(DetonationAction::Panic as u8);
+
+pub(crate) fn default_action() -> DetonationAction {
+    match DEFAULT_ACTION.load(Ordering::Relaxed) {
+        x if x == DetonationAction::Abort as u8 => DetonationAction::Abort,
+        x if x == DetonationAction::Warn as u8 => DetonationAction::Warn,
+        x if x == DetonationAction::Defer as u8 => DetonationAction::Defer,
+        _ => DetonationAction::Panic,
+    }
+}
+
+pub(crate) fn set_default_action(action: DetonationAction) {
+    DEFAULT_ACTION.store(action as u8, Ordering::Relaxed)
+}
+
+/// A thread's deferred detonations. Whatever is still pending when the
+/// thread exits is reported then, as nothing can flush it afterwards.
+struct Deferred(RefCell<Vec<String>>);
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        for msg in self.0.get_mut().drain(..) {
+            report(format_args!("{}", msg))
+        }
+    }
+}
+
+thread_local! {
+    static DEFERRED_DETONATIONS: Deferred = const { Deferred(RefCell::new(Vec::new())) };
+}
+
+pub(crate) fn defer_detonation(msg: String) {
+    let mut msg = Some(msg);
+    let _ = DEFERRED_DETONATIONS.try_with(|it| it.0.borrow_mut().extend(msg.take()));
+    // Thread-locals are already gone: nothing will ever flush, so
+    // report right away.
+    if let Some(msg) = msg {
+        report(format_args!("{}", msg))
+    }
+}
+
+pub(crate) fn take_deferred_detonations() -> Vec<String> {
+    DEFERRED_DETONATIONS.with(|it| mem::take(&mut *it.0.borrow_mut()))
+}
+
+thread_local! {
+    static DETONATING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether this thread is panicking because of `panic_detonation`.
+pub(crate) fn detonating() -> bool {
+    DETONATING.try_with(Cell::get).unwrap_or(false)
+}
+
+/// Panics with `msg`, flagging the panic as a detonation for as long
+/// as it unwinds through the bomb's `drop`, which covers the panic
+/// hook.
+#[track_caller]
+pub(crate) fn panic_detonation(msg: &str) -> ! {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            let _ = DETONATING.try_with(|it| it.set(false));
+        }
+    }
+    let _ = DETONATING.try_with(|it| it.set(true));
+    let _reset = Reset;
+    panic!("{}", msg)
+}
+
+type Writer = Box<dyn Write + Send + Sync>;
+
+static WRITER: Mutex<Option<Writer>> = Mutex::new(None);
+
+pub(crate) fn set_writer(writer: Writer) {
+    *WRITER.lock().unwrap_or_else(|err| err.into_inner()) = Some(writer);
+}
+
+/// Prints a line of detonation output, to stderr unless redirected
+/// with `set_writer`. Errors are ignored: there's nowhere to report
+/// them.
+pub(crate) fn report(args: fmt::Arguments) {
+    let mut writer = WRITER.lock().unwrap_or_else(|err| err.into_inner());
+    let _ = match *writer {
+        Some(ref mut writer) => writeln!(writer, "{}", args).and_then(|()| writer.flush()),
+        None => writeln!(io::stderr(), "{}", args),
+    };
+}
+
+static DETONATIONS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "metrics")]
+static DETONATIONS_BY_MESSAGE: OnceLock<Mutex<HashMap<String, usize>>> = OnceLock::new();
+
+#[cfg(feature = "metrics")]
+fn detonations_by_message() -> ::std::sync::MutexGuard<'static, HashMap<String, usize>> {
+    DETONATIONS_BY_MESSAGE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+thread_local! {
+    static SCOPED_DETONATIONS: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Starts counting this thread's detonations from zero, returning the
+/// count of the enclosing scope, if any.
+pub(crate) fn push_scoped_counter() -> Option<usize> {
+    SCOPED_DETONATIONS.with(|it| it.replace(Some(0)))
+}
+
+/// Stops counting, returning the count. The detonations are added to
+/// the enclosing scope's count.
+pub(crate) fn pop_scoped_counter(outer: Option<usize>) -> usize {
+    let count = scoped_detonations();
+    let _ = SCOPED_DETONATIONS.try_with(|it| it.set(outer.map(|outer| outer + count)));
+    count
+}
+
+pub(crate) fn scoped_detonations() -> usize {
+    SCOPED_DETONATIONS
+        .try_with(Cell::get)
+        .ok()
+        .flatten()
+        .unwrap_or(0)
+}
+
+pub(crate) fn record_detonation(msg: &str) {
+    DETONATIONS.fetch_add(1, Ordering::Relaxed);
+    let _ = SCOPED_DETONATIONS.try_with(|it| it.set(it.get().map(|count| count + 1)));
+    #[cfg(feature = "metrics")]
+    {
+        *detonations_by_message().entry(msg.to_string()).or_insert(0) += 1;
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = msg;
+}
+
+pub(crate) fn detonations() -> usize {
+    DETONATIONS.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn detonations_for(msg: &str) -> usize {
+    detonations_by_message().get(msg).cloned().unwrap_or(0)
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn reset_detonations() {
+    let mut by_message = detonations_by_message();
+    by_message.clear();
+    DETONATIONS.store(0, Ordering::Relaxed);
+}
+
+static CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn record_construction() {
+    CONSTRUCTED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_drop() {
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The number of bombs which were constructed but not (yet) dropped.
+pub(crate) fn undropped() -> usize {
+    let dropped = DROPPED.load(Ordering::Relaxed);
+    CONSTRUCTED.load(Ordering::Relaxed).saturating_sub(dropped)
+}
+
+#[cfg(feature = "debug_registry")]
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// Keyed by an id rather than by address, because bombs move.
+#[cfg(feature = "debug_registry")]
+static LIVE: OnceLock<Mutex<HashMap<u64, String>>> = OnceLock::new();
+
+#[cfg(feature = "debug_registry")]
+fn live() -> ::std::sync::MutexGuard<'static, HashMap<u64, String>> {
+    LIVE.get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(feature = "debug_registry")]
+pub(crate) fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg(feature = "debug_registry")]
+pub(crate) fn register(id: u64, msg: &str) {
+    live().insert(id, msg.to_string());
+}
+
+#[cfg(feature = "debug_registry")]
+pub(crate) fn unregister(id: u64) {
+    live().remove(&id);
+}
+
+/// The messages of the registered bombs, oldest first.
+#[cfg(feature = "debug_registry")]
+pub(crate) fn live_messages() -> Vec<String> {
+    let live = live();
+    let mut entries: Vec<(&u64, &String)> = live.iter().collect();
+    entries.sort();
+    entries.into_iter().map(|(_, msg)| msg.clone()).collect()
+}
+
+// Interned messages are leaked, so that bombs can keep borrowing them
+// as `&'static str`. The pool only grows by distinct messages.
+#[cfg(feature = "intern")]
+static INTERNED: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+
+#[cfg(feature = "intern")]
+pub(crate) fn intern(msg: String) -> &'static str {
+    let mut pool = INTERNED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    if let Some(&interned) = pool.get(msg.as_str()) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(msg.into_boxed_str());
+    pool.insert(interned);
+    interned
+}
+
+/// Restores everything above to its initial state, except for the
+/// construction and drop counters and the registry of live bombs, which
+/// track bombs that still exist. Deferred detonations are only cleared
+/// for the current thread.
+pub(crate) fn reset_all() {
+    ENABLED_STATE.store(UNKNOWN, Ordering::Relaxed);
+    RUNTIME_CHECKS.store(UNKNOWN, Ordering::Relaxed);
+    DEFAULT_ACTION.store(DetonationAction::Panic as u8, Ordering::Relaxed);
+    let _ = DEFERRED_DETONATIONS.try_with(|it| it.0.borrow_mut().clear());
+    *WRITER.lock().unwrap_or_else(|err| err.into_inner()) = None;
+    #[cfg(feature = "metrics")]
+    detonations_by_message().clear();
+    DETONATIONS.store(0, Ordering::Relaxed);
+}
+
+/// Held while the panic hook is temporarily replaced.
+#[cfg(feature = "test-util")]
+pub(crate) static PANIC_HOOK_LOCK: Mutex<()> = Mutex::new(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn enabled_is_consistent_across_threads() {
+        let handles: Vec<_> = (0..8).map(|_| thread::spawn(enabled)).collect();
+        let results: Vec<bool> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert!(results.iter().all(|&it| it == enabled()));
+    }
+
+    #[test]
+    fn counters_are_consistent_across_threads() {
+        let before = CONSTRUCTED.load(Ordering::Relaxed);
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..1000 {
+                        record_construction();
+                        record_drop();
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert!(CONSTRUCTED.load(Ordering::Relaxed) >= before + 8000);
+        assert!(DROPPED.load(Ordering::Relaxed) >= 8000);
+    }
+}
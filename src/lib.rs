@@ -38,169 +38,3445 @@
 //!
 //! ## Notes:
 //!
-//! * Bombs do nothing if a thread is already panicking.
+//! * Bombs do nothing if a thread is already panicking, unless
+//!   `DropBomb::set_detonate_during_panic` is used to abort instead.
 //! * When `#[cfg(debug_assertions)]` is disabled, `DebugDropBomb` is
 //!   always defused and has a zero size.
+//! * Setting `DROP_BOMB_DISABLE=1` in the environment turns all
+//!   detonations off. This is a safety valve for incident response,
+//!   for when a bomb fires in production and a rebuild is not an
+//!   option, not something to rely on in normal operation. The
+//!   variable is read once, the first time it is needed: when an armed
+//!   bomb is dropped, or by `is_enabled` or `is_armed`.
+//! * `AtomicDropBomb` can be defused through a shared reference, which
+//!   is handy when it is shared between threads.
+//! * `RuntimeBomb` is real or a no-op depending on
+//!   `DROP_BOMB_RUNTIME_CHECKS=1` (or `set_runtime_checks`), for
+//!   checking in release builds without recompiling.
+#![cfg_attr(feature = "nightly", feature(must_not_suspend))]
+use std::any::Any;
 use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::panic::Location;
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, PoisonError};
 
-#[derive(Debug)]
+mod atomic;
+#[cfg(feature = "compact")]
+mod code;
+mod global;
+pub mod linear;
+mod runtime;
+#[cfg(feature = "test-util")]
+mod test_util;
+
+pub use atomic::AtomicDropBomb;
+#[cfg(feature = "compact")]
+pub use code::CodeBomb;
+pub use runtime::RuntimeBomb;
+#[cfg(feature = "test-util")]
+pub use test_util::capture_detonation;
+
+// `has_significant_drop` opts bombs into clippy's
+// `significant_drop_in_scrutinee` and `significant_drop_tightening`
+// lints, which flag armed bombs held for longer than intended.
 #[must_use]
+#[clippy::has_significant_drop]
+#[cfg_attr(
+    feature = "nightly",
+    must_not_suspend = "an armed DropBomb should be defused before awaiting"
+)]
+#[cfg_attr(
+    feature = "nightly",
+    doc = r#"
+With the `nightly` feature, holding a bomb across an `.await` triggers
+the `must_not_suspend` lint:
+
+```compile_fail,edition2018
+#![feature(must_not_suspend)]
+#![deny(must_not_suspend)]
+extern crate drop_bomb;
+
+async fn yield_now() {}
+
+async fn close() {
+    let mut bomb = drop_bomb::DropBomb::new("Kaboom");
+    yield_now().await;
+    bomb.defuse();
+}
+
+fn main() {}
+```
+"#
+)]
 pub struct DropBomb(RealBomb);
 
 impl DropBomb {
+    /// The message used by `DropBomb::default()`.
+    pub const DEFAULT_MESSAGE: &str = "value must be explicitly consumed before being dropped";
+
+    /// Whether the bomb takes up no space. Always `false`.
+    pub const IS_ZERO_SIZED: bool = mem::size_of::<DropBomb>() == 0;
+
     pub fn new(msg: impl Into<Cow<'static, str>>) -> DropBomb {
-        DropBomb(RealBomb::new(msg.into()))
+        DropBomb::new_cow(msg.into())
+    }
+    /// Like `new`, but takes the `Cow` directly, which avoids a generic
+    /// instantiation per message type.
+    pub fn new_cow(msg: Cow<'static, str>) -> DropBomb {
+        DropBomb(RealBomb::new(msg))
+    }
+    /// Like `new`, but attributes the bomb to `location`, which is
+    /// included in the detonation message. For wrappers which forward
+    /// their own `#[track_caller]` location, so that a leak points at
+    /// the wrapper's caller.
+    pub fn new_at(
+        msg: impl Into<Cow<'static, str>>,
+        location: &'static Location<'static>,
+    ) -> DropBomb {
+        let mut res = DropBomb::new(msg);
+        res.0.created_at = Some(location);
+        res
+    }
+    /// Creates a bomb whose detonation message is laid out like a
+    /// compiler diagnostic, with the message, the location of the call
+    /// and a `help` line suggesting the fix.
+    #[track_caller]
+    pub fn with_help(msg: impl Into<Cow<'static, str>>, help: &'static str) -> DropBomb {
+        let mut res = DropBomb::new_at(msg, Location::caller());
+        res.set_help(help);
+        res
+    }
+    /// Like `new`, for a computed message which many bombs share: bombs
+    /// with identical interned messages share a single copy of the
+    /// text.
+    ///
+    /// Interned messages are never freed, so this is only a win for a
+    /// bounded set of distinct messages.
+    #[cfg(feature = "intern")]
+    pub fn new_interned(msg: String) -> DropBomb {
+        DropBomb::new_cow(Cow::Borrowed(global::intern(msg)))
+    }
+    /// Creates a bomb with the action for `severity`, instead of the
+    /// default one. `set_action` still overrides it.
+    pub fn with_severity(msg: impl Into<Cow<'static, str>>, severity: Severity) -> DropBomb {
+        let mut res = DropBomb::new(msg);
+        res.set_action(severity.action());
+        res
+    }
+    /// Like `new`, for a computed message.
+    pub fn with_message_owned(msg: String) -> DropBomb {
+        DropBomb::new_cow(Cow::Owned(msg))
+    }
+    /// Creates a bomb which, instead of panicking, prints a warning to
+    /// stderr when dropped armed. Detonations are still counted by
+    /// `detonation_count`.
+    ///
+    /// This is meant for introducing bombs into an existing codebase:
+    /// find all the leaks first, then `promote` the bombs to real ones.
+    pub fn new_dry_run(msg: impl Into<Cow<'static, str>>) -> DropBomb {
+        let mut res = DropBomb::new(msg);
+        res.set_action(DetonationAction::Warn);
+        res
     }
+    /// Like `new`, but rejects messages which are empty or consist only
+    /// of whitespace.
+    pub fn try_new(msg: impl Into<Cow<'static, str>>) -> Result<DropBomb, EmptyMessage> {
+        let msg = msg.into();
+        if msg.trim().is_empty() {
+            return Err(EmptyMessage);
+        }
+        Ok(DropBomb::new(msg))
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
     pub fn defuse(&mut self) {
         self.set_defused(true)
     }
+    pub fn arm(&mut self) {
+        self.set_defused(false)
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
     pub fn set_defused(&mut self, defused: bool) {
         self.0.set_defused(defused)
     }
     pub fn is_defused(&self) -> bool {
         self.0.is_defused()
     }
+    /// Whether dropping the bomb now would detonate it: the bomb is
+    /// armed, and detonation is not globally disabled.
+    pub fn is_armed(&self) -> bool {
+        self.is_really_armed() && global::enabled()
+    }
+    /// Whether the bomb itself is armed, regardless of the global switch.
+    pub fn is_really_armed(&self) -> bool {
+        !self.is_defused()
+    }
+    pub fn message(&self) -> &str {
+        self.0.message()
+    }
+    pub fn set_message(&mut self, msg: impl Into<Cow<'static, str>>) {
+        self.set_message_cow(msg.into())
+    }
+    /// Like `set_message`, but takes the `Cow` directly.
+    pub fn set_message_cow(&mut self, msg: Cow<'static, str>) {
+        self.0.set_message(msg)
+    }
+    /// Re-arms the bomb, replacing its message.
+    pub fn arm_with(&mut self, msg: impl Into<Cow<'static, str>>) {
+        self.0.arm_with(msg.into())
+    }
+    /// By default, an armed bomb dropped while the thread is already
+    /// panicking does nothing. With `detonate_during_panic` set, it
+    /// prints its message and aborts the process instead, so that a
+    /// leaked critical bomb is never masked by an unrelated panic.
+    pub fn set_detonate_during_panic(&mut self, detonate_during_panic: bool) {
+        self.0.detonate_during_panic = detonate_during_panic
+    }
+    /// Returns exactly the message `drop` would panic with if the bomb
+    /// were dropped armed, without detonating it.
+    pub fn detonation_message(&self) -> String {
+        self.0.detonation_message()
+    }
+    /// Same as `armed_message`.
+    pub fn as_option(&self) -> Option<&str> {
+        self.armed_message()
+    }
+    /// Replaces the message, but only if the bomb is still armed.
+    /// Returns whether the message was replaced.
+    pub fn set_message_if_armed(&mut self, msg: impl Into<Cow<'static, str>>) -> bool {
+        if self.is_defused() {
+            return false;
+        }
+        self.0.set_message(msg.into());
+        true
+    }
+    /// Combines two bombs into one, for when two linear values are
+    /// folded into a composite. Both inputs are defused; the result is
+    /// armed if either input was, and its message lists both. Its
+    /// action is the stricter of the two: `Abort` over `Panic` over
+    /// `Defer` over `Warn`.
+    pub fn merge(mut self, mut other: DropBomb) -> DropBomb {
+        let mut merged = DropBomb::new(format!("{}\n{}", self.0.message(), other.0.message()));
+        if self.is_defused() && other.is_defused() {
+            merged.0.disarm();
+        }
+        merged.0.action = self.0.action.stricter(other.0.action);
+        merged.0.detonate_during_panic =
+            self.0.detonate_during_panic || other.0.detonate_during_panic;
+        merged.0.on_defuse = match (self.0.on_defuse.take(), other.0.on_defuse.take()) {
+            (Some(a), Some(b)) => {
+                let a = a.into_inner().unwrap_or_else(PoisonError::into_inner);
+                let b = b.into_inner().unwrap_or_else(PoisonError::into_inner);
+                Some(Mutex::new(Box::new(move || {
+                    a();
+                    b()
+                })))
+            }
+            (a, b) => a.or(b),
+        };
+        self.0.disarm();
+        other.0.disarm();
+        merged
+    }
+    /// Converts the bomb into a `DebugDropBomb`, carrying over the
+    /// message and armed state. In release builds the result is the
+    /// usual always-defused `DebugDropBomb`.
+    pub fn into_debug(mut self) -> DebugDropBomb {
+        let mut res = DebugDropBomb::new(self.0.take_message());
+        if self.is_defused() {
+            res.0.disarm();
+        }
+        self.0.disarm();
+        res
+    }
+    /// Same as `into_debug`.
+    pub fn downgrade(self) -> DebugDropBomb {
+        self.into_debug()
+    }
+    /// Decomposes the bomb into its message and defused state, without
+    /// detonating it. The inverse of `from_parts`.
+    pub fn into_parts(mut self) -> (Cow<'static, str>, bool) {
+        let parts = (self.0.take_message(), self.is_defused());
+        self.0.disarm();
+        parts
+    }
+    pub fn from_parts(msg: Cow<'static, str>, defused: bool) -> DropBomb {
+        let mut res = DropBomb::new(msg);
+        if defused {
+            res.0.disarm();
+        }
+        res
+    }
+    /// Seals the bomb: once it is defused (or right away, if it already
+    /// is), it stays defused for good, and attempts to re-arm it via
+    /// `arm`, `set_defused(false)` or `arm_with` are ignored.
+    pub fn seal(&mut self) {
+        self.0.seal()
+    }
+    /// Returns the message, or `fallback` if the message is empty.
+    pub fn message_or<'a>(&'a self, fallback: &'a str) -> &'a str {
+        match self.message() {
+            "" => fallback,
+            msg => msg,
+        }
+    }
+    /// Appends `suffix` to the message. A borrowed message is copied
+    /// into an owned one with twice the needed capacity, so that later
+    /// appends extend it in place until it has doubled in length.
+    pub fn append_message(&mut self, suffix: &str) {
+        self.0.append_message(suffix)
+    }
+    /// Defuses the bomb and returns `value`, for close routines ending
+    /// with `Ok(self.bomb.defuse_returning(result))`.
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn defuse_returning<T>(&mut self, value: T) -> T {
+        self.defuse();
+        value
+    }
+    /// Turns a dry-run bomb into one which panics.
+    pub fn promote(&mut self) {
+        self.set_action(DetonationAction::Panic)
+    }
+    /// Defuses the bomb, returning `true` if it was armed and `false` if
+    /// it had already been defused. Useful for catching cleanup code
+    /// which is reachable more than once.
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn defuse_once(&mut self) -> bool {
+        let was_armed = !self.is_defused();
+        self.defuse();
+        was_armed
+    }
+    /// Returns `Some(message)` if the bomb is armed, and `None` if it
+    /// is defused.
+    ///
+    /// This is the way to collect outstanding obligations:
+    ///
+    /// ```rust
+    /// extern crate drop_bomb;
+    ///
+    /// use drop_bomb::DropBomb;
+    ///
+    /// let mut bombs = vec![
+    ///     DropBomb::new("flush the log"),
+    ///     DropBomb::new("close the socket"),
+    /// ];
+    /// bombs[0].defuse();
+    /// let outstanding: Vec<&str> = bombs.iter().filter_map(DropBomb::armed_message).collect();
+    /// assert_eq!(outstanding, ["close the socket"]);
+    /// # bombs[1].defuse();
+    /// ```
+    pub fn armed_message(&self) -> Option<&str> {
+        if self.is_defused() {
+            None
+        } else {
+            Some(self.message())
+        }
+    }
+    /// Panics with the detonation message right away if the bomb is
+    /// armed, for asserting progress at a checkpoint. The bomb itself is
+    /// left as is.
+    #[track_caller]
+    pub fn panic_immediately_if_armed(&self) {
+        if !self.is_defused() {
+            panic!("{}", self.detonation_message())
+        }
+    }
+    /// Panics with `msg` if the bomb is defused, for asserting that an
+    /// obligation is still outstanding. The bomb itself is left as is.
+    #[track_caller]
+    pub fn expect_armed(&self, msg: &str) {
+        if self.is_defused() {
+            panic!("{}", msg)
+        }
+    }
+    /// Sets what the bomb does when dropped armed, overriding the
+    /// default set by `set_default_action`.
+    pub fn set_action(&mut self, action: DetonationAction) {
+        self.0.action = action
+    }
+    pub fn action(&self) -> DetonationAction {
+        self.0.action
+    }
+    /// Replaces the action with `f` applied to the current one.
+    pub fn map_action(&mut self, f: impl FnOnce(DetonationAction) -> DetonationAction) {
+        self.0.action = f(self.0.action)
+    }
+    /// Sets the action, returning the previous one.
+    pub fn replace_action(&mut self, action: DetonationAction) -> DetonationAction {
+        mem::replace(&mut self.0.action, action)
+    }
+    /// Runs `f` under `catch_unwind`, defusing the bomb if it returns
+    /// normally. If `f` panics, the panic is caught and returned, and
+    /// the bomb stays armed.
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn catch<R>(
+        &mut self,
+        f: impl FnOnce() -> R + ::std::panic::UnwindSafe,
+    ) -> ::std::thread::Result<R> {
+        let res = ::std::panic::catch_unwind(f);
+        if res.is_ok() {
+            self.defuse();
+        }
+        res
+    }
+    /// Runs `f`, defusing the bomb if it returns `Ok`. On `Err`, or if
+    /// `f` panics, the bomb stays armed.
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn run<T, E>(&mut self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let res = f();
+        if res.is_ok() {
+            self.defuse();
+        }
+        res
+    }
+    /// The length of the message in bytes.
+    pub fn message_len(&self) -> usize {
+        self.message().len()
+    }
+    /// Returns a defused bomb with the same message and configuration as
+    /// this one, for using a bomb as a template. The `on_defuse` hook and
+    /// the context can't be cloned, and are left out. The clone is not
+    /// sealed.
+    pub fn clone_disarmed(&self) -> DropBomb {
+        let mut res = DropBomb::new_cow(self.0.msg.clone());
+        res.0.detonate_during_panic = self.0.detonate_during_panic;
+        res.0.action = self.0.action;
+        res.0.help = self.0.help;
+        res.0.created_at = self.0.created_at;
+        res.0.disarm();
+        res
+    }
+    /// Defuses the bomb if `result` is `Ok`, leaving it armed on `Err`.
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn defuse_if_ok<T, E>(&mut self, result: &Result<T, E>) {
+        if result.is_ok() {
+            self.defuse();
+        }
+    }
+    /// Sets a hook to run when the bomb next goes from armed to defused.
+    /// The hook runs at most once; re-arming doesn't restore it.
+    ///
+    /// Consuming the bomb with `into_parts` or `into_debug` drops the
+    /// hook without running it, and `merge` moves it to the merged bomb.
+    pub fn set_on_defuse(&mut self, f: impl FnOnce() + Send + 'static) {
+        self.0.on_defuse = Some(Mutex::new(Box::new(f)))
+    }
+    pub fn message_eq(&self, expected: &str) -> bool {
+        self.message() == expected
+    }
+    /// Stashes `ctx` on the bomb, replacing any previous context, for
+    /// retrieving it later with `take_context`.
+    pub fn set_context<T: Any + Send>(&mut self, ctx: T) {
+        self.0.context = Some(Mutex::new(Box::new(ctx)))
+    }
+    /// Removes and returns the context if it is a `T`. A context of a
+    /// different type is left in place.
+    pub fn take_context<T: Any>(&mut self) -> Option<T> {
+        let ctx = self.0.context.take()?;
+        match ctx
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .downcast::<T>()
+        {
+            Ok(ctx) => Some(*ctx),
+            Err(ctx) => {
+                self.0.context = Some(Mutex::new(ctx));
+                None
+            }
+        }
+    }
+    /// Arms the bomb and couples it with `value` for a scope. Committing
+    /// the returned guard defuses the bomb and gives `value` back;
+    /// dropping the guard leaves the bomb armed.
+    pub fn scope<T>(&mut self, value: T) -> ScopeGuard<'_, T> {
+        self.arm();
+        ScopeGuard { bomb: self, value }
+    }
+    /// Like `defuse_if_ok`, for a result which is still being
+    /// accumulated into. The result is not modified.
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn defuse_on_ok_mut<T, E>(&mut self, result: &mut Result<T, E>) {
+        self.defuse_if_ok(result)
+    }
+    /// Adds a `help` line to the detonation message, switching it to the
+    /// layout of `with_help`.
+    pub fn set_help(&mut self, help: &'static str) {
+        self.0.help = Some(help)
+    }
+    /// Sets the message to `value`, formatted right away.
+    pub fn set_message_from<D: fmt::Display>(&mut self, value: D) {
+        self.set_message(value.to_string())
+    }
+    /// Whether both bombs' messages are the same text in memory, as for
+    /// interned messages or a shared `&'static str`. Equal owned
+    /// messages are separate copies and don't count. Empty messages
+    /// don't allocate, so they only match when both are the same bomb.
+    pub fn message_ptr_eq(&self, other: &DropBomb) -> bool {
+        if self.message().is_empty() {
+            return ::std::ptr::eq(self, other);
+        }
+        ::std::ptr::eq(self.message(), other.message())
+    }
+    /// Drops the `on_defuse` hook, context, help and location, unseals
+    /// the bomb, and resets the action to `Panic` and
+    /// `set_detonate_during_panic` to `false`. Only the message and
+    /// armed state are kept.
+    pub fn clear_config(&mut self) {
+        self.0.sealed = false;
+        self.0.on_defuse = None;
+        self.0.context = None;
+        self.0.help = None;
+        self.0.created_at = None;
+        self.0.detonate_during_panic = false;
+        self.0.action = DetonationAction::Panic;
+    }
 }
 
-#[derive(Debug)]
 #[must_use]
+#[cfg_attr(debug_assertions, clippy::has_significant_drop)]
 pub struct DebugDropBomb(DebugBomb);
 
 impl DebugDropBomb {
+    pub const DEFAULT_MESSAGE: &str = DropBomb::DEFAULT_MESSAGE;
+
+    /// Whether the bomb takes up no space, which is the case in release
+    /// builds.
+    pub const IS_ZERO_SIZED: bool = mem::size_of::<DebugDropBomb>() == 0;
+
     pub fn new(msg: impl Into<Cow<'static, str>>) -> DebugDropBomb {
-        DebugDropBomb(DebugBomb::new(msg.into()))
+        DebugDropBomb::new_cow(msg.into())
+    }
+    pub fn new_cow(msg: Cow<'static, str>) -> DebugDropBomb {
+        DebugDropBomb(DebugBomb::new(msg))
+    }
+    pub fn with_message_owned(msg: String) -> DebugDropBomb {
+        DebugDropBomb::new_cow(Cow::Owned(msg))
     }
+    pub fn try_new(msg: impl Into<Cow<'static, str>>) -> Result<DebugDropBomb, EmptyMessage> {
+        let msg = msg.into();
+        if msg.trim().is_empty() {
+            return Err(EmptyMessage);
+        }
+        Ok(DebugDropBomb::new(msg))
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
     pub fn defuse(&mut self) {
         self.set_defused(true)
     }
+    pub fn arm(&mut self) {
+        self.set_defused(false)
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
     pub fn set_defused(&mut self, defused: bool) {
         self.0.set_defused(defused)
     }
     pub fn is_defused(&self) -> bool {
         self.0.is_defused()
     }
+    pub fn is_armed(&self) -> bool {
+        self.is_really_armed() && global::enabled()
+    }
+    pub fn is_really_armed(&self) -> bool {
+        !self.is_defused()
+    }
+    pub fn message(&self) -> &str {
+        self.0.message()
+    }
+    pub fn set_message(&mut self, msg: impl Into<Cow<'static, str>>) {
+        self.set_message_cow(msg.into())
+    }
+    pub fn set_message_cow(&mut self, msg: Cow<'static, str>) {
+        self.0.set_message(msg)
+    }
+    pub fn arm_with(&mut self, msg: impl Into<Cow<'static, str>>) {
+        self.0.arm_with(msg.into())
+    }
+    pub fn detonation_message(&self) -> String {
+        self.0.detonation_message()
+    }
+    pub fn as_option(&self) -> Option<&str> {
+        self.armed_message()
+    }
+    pub fn set_message_if_armed(&mut self, msg: impl Into<Cow<'static, str>>) -> bool {
+        if self.is_defused() {
+            return false;
+        }
+        self.0.set_message(msg.into());
+        true
+    }
+    pub fn merge(mut self, mut other: DebugDropBomb) -> DebugDropBomb {
+        let mut merged = DebugDropBomb::new(format!("{}\n{}", self.0.message(), other.0.message()));
+        if self.is_defused() && other.is_defused() {
+            merged.0.disarm();
+        }
+        self.0.disarm();
+        other.0.disarm();
+        merged
+    }
+    pub fn into_parts(mut self) -> (Cow<'static, str>, bool) {
+        let parts = (self.0.take_message(), self.is_defused());
+        self.0.disarm();
+        parts
+    }
+    pub fn from_parts(msg: Cow<'static, str>, defused: bool) -> DebugDropBomb {
+        let mut res = DebugDropBomb::new(msg);
+        if defused {
+            res.0.disarm();
+        }
+        res
+    }
+    pub fn seal(&mut self) {
+        self.0.seal()
+    }
+    /// Returns the message, or `fallback` if the message is empty, as
+    /// it always is in release builds.
+    pub fn message_or<'a>(&'a self, fallback: &'a str) -> &'a str {
+        match self.message() {
+            "" => fallback,
+            msg => msg,
+        }
+    }
+    pub fn append_message(&mut self, suffix: &str) {
+        self.0.append_message(suffix)
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn defuse_returning<T>(&mut self, value: T) -> T {
+        self.defuse();
+        value
+    }
+    /// In release builds `DebugDropBomb` is always defused, so this
+    /// always returns `false` there.
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn defuse_once(&mut self) -> bool {
+        let was_armed = !self.is_defused();
+        self.defuse();
+        was_armed
+    }
+    pub fn armed_message(&self) -> Option<&str> {
+        if self.is_defused() {
+            None
+        } else {
+            Some(self.message())
+        }
+    }
+    #[track_caller]
+    pub fn panic_immediately_if_armed(&self) {
+        if !self.is_defused() {
+            panic!("{}", self.detonation_message())
+        }
+    }
+    /// Panics with `msg` if the bomb is defused. In release builds a
+    /// `DebugDropBomb` is always defused, so this always panics there.
+    #[track_caller]
+    pub fn expect_armed(&self, msg: &str) {
+        if self.is_defused() {
+            panic!("{}", msg)
+        }
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn run<T, E>(&mut self, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let res = f();
+        if res.is_ok() {
+            self.defuse();
+        }
+        res
+    }
+    pub fn message_len(&self) -> usize {
+        self.message().len()
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn defuse_if_ok<T, E>(&mut self, result: &Result<T, E>) {
+        if result.is_ok() {
+            self.defuse();
+        }
+    }
+    /// Compares the message against `expected`. In release builds the
+    /// message is always empty.
+    pub fn message_eq(&self, expected: &str) -> bool {
+        self.message() == expected
+    }
+    /// Converts the bomb into a `DropBomb`, carrying over the message and
+    /// armed state. In release builds there is no state to carry: the
+    /// result is a defused `DropBomb` with an empty message.
+    pub fn into_real(self) -> DropBomb {
+        let (msg, defused) = self.into_parts();
+        DropBomb::from_parts(msg, defused)
+    }
+    pub fn set_message_from<D: fmt::Display>(&mut self, value: D) {
+        self.set_message(value.to_string())
+    }
 }
 
-#[cfg(debug_assertions)]
-type DebugBomb = RealBomb;
-#[cfg(not(debug_assertions))]
-type DebugBomb = FakeBomb;
+/// A zero-sized marker for values which are linear by convention only.
+///
+/// `PhantomBomb` only has `new`, `defuse` and `is_defused`, so code
+/// using just those can switch to or from a `DropBomb`. It propagates
+/// `#[must_use]`, but it never panics and has zero size in every
+/// profile. Like a release-mode `DebugDropBomb`, it is always defused.
+#[derive(Debug, Default)]
+#[must_use]
+pub struct PhantomBomb(());
 
-#[derive(Debug)]
-struct RealBomb {
-    msg: Cow<'static, str>,
-    defused: bool,
+impl PhantomBomb {
+    pub fn new(_msg: impl Into<Cow<'static, str>>) -> PhantomBomb {
+        PhantomBomb(())
+    }
+    pub fn defuse(&mut self) {}
+    pub fn is_defused(&self) -> bool {
+        true
+    }
 }
 
-impl RealBomb {
-    fn new(msg: Cow<'static, str>) -> RealBomb {
-        RealBomb {
-            msg: msg.into(),
-            defused: false,
-        }
+// Formatting only reads the bomb, so it is safe to `{:?}` a bomb from
+// panic hooks and other `Debug` impls.
+impl fmt::Debug for DropBomb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DropBomb")
+            .field("message", &self.0.message())
+            .field("defused", &self.0.is_defused())
+            .finish()
+    }
+}
+
+impl fmt::Debug for DebugDropBomb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DebugDropBomb")
+            .field("message", &self.0.message())
+            .field("defused", &self.0.is_defused())
+            .finish()
+    }
+}
+
+/// Displays the bomb as `DropBomb(armed): "message"`. With the
+/// `track-defuse` feature, a defused bomb also shows where it was last
+/// defused: `DropBomb(defused at src/lib.rs:92): "message"`.
+impl fmt::Display for DropBomb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("DropBomb(")?;
+        self.0.fmt_state(f)?;
+        write!(f, "): {:?}", self.message())
+    }
+}
+
+impl fmt::Display for DebugDropBomb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("DebugDropBomb(")?;
+        self.0.fmt_state(f)?;
+        write!(f, "): {:?}", self.message())
     }
+}
+
+impl Default for DropBomb {
+    fn default() -> DropBomb {
+        DropBomb::new(DropBomb::DEFAULT_MESSAGE)
+    }
+}
+
+impl Default for DebugDropBomb {
+    fn default() -> DebugDropBomb {
+        DebugDropBomb::new(DebugDropBomb::DEFAULT_MESSAGE)
+    }
+}
+
+/// The operations shared by `DropBomb` and `DebugDropBomb`, for code
+/// which stores both kinds, for example as `Vec<Box<dyn Bomb>>`.
+pub trait Bomb: Send + Sync {
+    fn defuse(&mut self);
+    fn set_defused(&mut self, defused: bool);
+    fn is_defused(&self) -> bool;
+    fn message(&self) -> &str;
+}
+
+impl Bomb for DropBomb {
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    fn defuse(&mut self) {
+        DropBomb::defuse(self)
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
     fn set_defused(&mut self, defused: bool) {
-        self.defused = defused
+        DropBomb::set_defused(self, defused)
     }
     fn is_defused(&self) -> bool {
-        self.defused
+        DropBomb::is_defused(self)
+    }
+    fn message(&self) -> &str {
+        DropBomb::message(self)
     }
 }
 
-impl Drop for RealBomb {
-    fn drop(&mut self) {
-        if !self.defused && !::std::thread::panicking() {
-            panic!("{}", self.msg)
-        }
+impl Bomb for DebugDropBomb {
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    fn defuse(&mut self) {
+        DebugDropBomb::defuse(self)
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    fn set_defused(&mut self, defused: bool) {
+        DebugDropBomb::set_defused(self, defused)
+    }
+    fn is_defused(&self) -> bool {
+        DebugDropBomb::is_defused(self)
+    }
+    fn message(&self) -> &str {
+        DebugDropBomb::message(self)
     }
 }
 
-#[derive(Debug)]
-#[cfg(not(debug_assertions))]
-struct FakeBomb {}
+/// Creates a `DebugDropBomb` if `debug` is set and a `DropBomb`
+/// otherwise, behind a `Bomb` trait object.
+pub fn new_boxed(msg: impl Into<Cow<'static, str>>, debug: bool) -> Box<dyn Bomb> {
+    if debug {
+        Box::new(DebugDropBomb::new(msg))
+    } else {
+        Box::new(DropBomb::new(msg))
+    }
+}
 
-#[cfg(not(debug_assertions))]
-impl FakeBomb {
-    fn new(_msg: Cow<'static, str>) -> FakeBomb {
-        FakeBomb {}
+/// What an armed `DropBomb` does when it is dropped.
+///
+/// Whatever the action, a bomb dropped while its thread is already
+/// panicking does nothing (see `DropBomb::set_detonate_during_panic`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetonationAction {
+    /// Panic with the bomb's message. This is the default.
+    Panic,
+    /// Print the message to stderr and abort the process.
+    Abort,
+    /// Print the message to stderr as a warning and carry on.
+    Warn,
+    /// Record the message in a per-thread list of pending detonations
+    /// and carry on. `flush_detonations` reports them all at once;
+    /// any still pending when the thread exits are reported then.
+    ///
+    /// This is useful with `panic = "abort"`, where the first panicking
+    /// bomb would otherwise take down the process before the others had
+    /// a chance to report.
+    Defer,
+}
+
+impl DetonationAction {
+    fn strictness(self) -> u8 {
+        match self {
+            DetonationAction::Warn => 0,
+            DetonationAction::Defer => 1,
+            DetonationAction::Panic => 2,
+            DetonationAction::Abort => 3,
+        }
     }
-    fn set_defused(&mut self, _defused: bool) {}
-    fn is_defused(&self) -> bool {
-        true
+    fn stricter(self, other: DetonationAction) -> DetonationAction {
+        if other.strictness() > self.strictness() {
+            other
+        } else {
+            self
+        }
     }
 }
 
-#[cfg(not(debug_assertions))]
-impl Drop for FakeBomb {
-    fn drop(&mut self) {}
+/// How critical an obligation is, for `DropBomb::with_severity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Detonates with `DetonationAction::Warn`.
+    Warn,
+    /// Detonates with `DetonationAction::Panic`.
+    Error,
+    /// Detonates with `DetonationAction::Abort`.
+    Fatal,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl Severity {
+    pub fn action(self) -> DetonationAction {
+        match self {
+            Severity::Warn => DetonationAction::Warn,
+            Severity::Error => DetonationAction::Panic,
+            Severity::Fatal => DetonationAction::Abort,
+        }
+    }
+}
 
-    #[test]
-    #[should_panic(expected = "Kaboom")]
-    fn armed_bomb_bombs() {
-        let _b = DropBomb::new("Kaboom");
+/// The error returned by `try_new` for an empty or blank message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmptyMessage;
+
+impl fmt::Display for EmptyMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("drop bomb message is empty")
     }
+}
 
-    #[test]
-    fn defused_bomb_is_safe() {
-        let mut b = DropBomb::new("Kaboom");
-        assert!(!b.is_defused());
-        b.defuse();
-        assert!(b.is_defused());
+impl ::std::error::Error for EmptyMessage {}
+
+/// The error returned by `defuse_nth` for an index out of bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexError {
+    pub index: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "drop bomb index {} is out of bounds for {} bomb(s)",
+            self.index, self.len
+        )
     }
+}
 
-    #[test]
-    #[should_panic(expected = r#"printf("sucks to be you"); exit(666);"#)]
-    fn no_double_panics() {
-        let _b = DropBomb::new("Kaboom");
-        panic!(r#"printf("sucks to be you"); exit(666);"#)
+impl ::std::error::Error for IndexError {}
+
+/// Defuses both bombs of a coupled pair.
+///
+/// Use this for resources which must be cleaned up together, so that
+/// one half of the pair can't be defused while the other is forgotten.
+#[cfg_attr(feature = "track-defuse", track_caller)]
+pub fn defuse_pair(a: &mut DropBomb, b: &mut DropBomb) {
+    a.defuse();
+    b.defuse();
+}
+
+/// Defuses both bombs of a coupled pair if `result` is `Ok`, leaving
+/// both armed otherwise. The result is passed through unchanged.
+#[cfg_attr(feature = "track-defuse", track_caller)]
+pub fn defuse_pair_on_ok<T, E>(
+    a: &mut DropBomb,
+    b: &mut DropBomb,
+    result: Result<T, E>,
+) -> Result<T, E> {
+    if result.is_ok() {
+        defuse_pair(a, b);
     }
+    result
+}
 
-    #[test]
-    #[should_panic(expected = "Kaboom")]
-    #[cfg(debug_assertions)]
-    fn debug_bomb_bombs_if_debug() {
-        let _b = DebugDropBomb::new("Kaboom");
+/// Defuses every bomb in a fixed-size array.
+#[cfg_attr(feature = "track-defuse", track_caller)]
+pub fn defuse_array<const N: usize>(bombs: &mut [DropBomb; N]) {
+    // A plain loop, so that `track_caller` reaches `defuse`.
+    for bomb in bombs {
+        bomb.defuse();
     }
+}
 
-    #[test]
-    #[cfg(not(debug_assertions))]
-    fn debug_bomb_bombs_if_debug() {
-        let _b = DebugDropBomb::new("Kaboom");
+/// Defuses the bomb at `index`, or returns an error if there is none.
+#[cfg_attr(feature = "track-defuse", track_caller)]
+pub fn defuse_nth(bombs: &mut [DropBomb], index: usize) -> Result<(), IndexError> {
+    let len = bombs.len();
+    match bombs.get_mut(index) {
+        Some(bomb) => {
+            bomb.defuse();
+            Ok(())
+        }
+        None => Err(IndexError { index, len }),
     }
+}
 
-    #[test]
-    fn defused_bomb_is_safe_if_debug() {
-        let mut b = DebugDropBomb::new("Kaboom");
-        #[cfg(debug_assertions)]
-        assert!(!b.is_defused());
-        #[cfg(not(debug_assertions))]
-        assert!(b.is_defused());
-        b.defuse();
-        assert!(b.is_defused());
+/// Re-arms every bomb in `bombs`.
+pub fn arm_all<'a>(bombs: impl IntoIterator<Item = &'a mut DropBomb>) {
+    for bomb in bombs {
+        bomb.arm();
     }
+}
 
-    #[test]
-    #[should_panic(expected = r#"printf("sucks to be you"); exit(666);"#)]
-    fn no_double_panics_if_debug() {
-        let _b = DebugDropBomb::new("Kaboom");
-        panic!(r#"printf("sucks to be you"); exit(666);"#)
+/// Drops the defused bombs in `bombs`, keeping the armed ones in order.
+pub fn retain_armed(bombs: &mut Vec<DropBomb>) {
+    bombs.retain(|it| !it.is_defused())
+}
+
+/// Returns a guard which defuses every bomb in `bombs` when it goes out
+/// of scope normally. If the scope is exited by a panic, the bombs are
+/// left armed.
+///
+/// With `track-defuse`, the bombs are recorded as defused where
+/// `guard_all` was called.
+#[cfg_attr(feature = "track-defuse", track_caller)]
+pub fn guard_all(bombs: &mut Vec<DropBomb>) -> VecDefuseGuard<'_> {
+    VecDefuseGuard {
+        bombs,
+        #[cfg(feature = "track-defuse")]
+        at: Location::caller(),
     }
+}
 
-    #[test]
-    #[cfg(not(debug_assertions))]
-    fn debug_bomb_is_zst() {
-        assert_eq!(::std::mem::size_of::<DebugDropBomb>(), 0);
+/// The guard returned by `guard_all`. Derefs to the guarded `Vec`.
+#[must_use]
+pub struct VecDefuseGuard<'a> {
+    bombs: &'a mut Vec<DropBomb>,
+    #[cfg(feature = "track-defuse")]
+    at: &'static Location<'static>,
+}
+
+impl<'a> ::std::ops::Deref for VecDefuseGuard<'a> {
+    type Target = Vec<DropBomb>;
+    fn deref(&self) -> &Vec<DropBomb> {
+        self.bombs
     }
+}
 
-    #[test]
-    fn check_traits() {
-        fn assert_traits<T: ::std::fmt::Debug + Send + Sync>() {}
-        assert_traits::<DropBomb>();
+impl<'a> ::std::ops::DerefMut for VecDefuseGuard<'a> {
+    fn deref_mut(&mut self) -> &mut Vec<DropBomb> {
+        self.bombs
+    }
+}
+
+impl<'a> Drop for VecDefuseGuard<'a> {
+    fn drop(&mut self) {
+        if ::std::thread::panicking() {
+            return;
+        }
+        for bomb in self.bombs.iter_mut() {
+            #[cfg(feature = "track-defuse")]
+            let was_armed = !bomb.is_defused();
+            bomb.defuse();
+            #[cfg(feature = "track-defuse")]
+            {
+                if was_armed {
+                    bomb.0.defused_at = Some(self.at);
+                }
+            }
+        }
+    }
+}
+
+/// Lists the messages of the armed bombs among `bombs`, one per line,
+/// or returns "all clear" if there are none.
+pub fn report(bombs: &[&dyn Bomb]) -> String {
+    let armed: Vec<&str> = bombs
+        .iter()
+        .filter(|it| !it.is_defused())
+        .map(|it| it.message())
+        .collect();
+    if armed.is_empty() {
+        return "all clear".to_string();
+    }
+    let mut res = format!("{} outstanding obligations:", armed.len());
+    for msg in armed {
+        res.push_str("\n - ");
+        res.push_str(msg);
+    }
+    res
+}
+
+/// The guard returned by `DropBomb::scope`. Derefs to the value.
+#[must_use]
+pub struct ScopeGuard<'a, T> {
+    bomb: &'a mut DropBomb,
+    value: T,
+}
+
+impl<'a, T> ScopeGuard<'a, T> {
+    /// Defuses the bomb and returns the value.
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn commit(self) -> T {
+        let ScopeGuard { bomb, value } = self;
+        bomb.defuse();
+        value
+    }
+}
+
+impl<'a, T> ::std::ops::Deref for ScopeGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<'a, T> ::std::ops::DerefMut for ScopeGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// A type guarded by a bomb, which must be consumed by `close`, like
+/// `CheckedBufWriter` in the crate docs.
+pub trait CloseChecked {
+    type Ok;
+    type Err;
+    /// Defuses the bomb and does the cleanup, reporting its errors.
+    fn close(self) -> Result<Self::Ok, Self::Err>;
+}
+
+/// Defuses `bomb` and runs the cleanup `f`, returning its result. The
+/// bomb is defused even if `f` fails: the obligation was to handle the
+/// error, and it is handed to the caller.
+#[cfg_attr(feature = "track-defuse", track_caller)]
+pub fn close_with<T, E>(bomb: &mut DropBomb, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    bomb.defuse();
+    f()
+}
+
+/// Arms `bomb` for the duration of `body` and defuses it if `body`
+/// finishes normally. If `body` panics, or leaves early via `return` or
+/// `?`, the bomb stays armed. Evaluates to the value of `body`.
+///
+/// `bomb` is named twice, so it must be a variable or a field path like
+/// `self.bomb`, which `body` may also use, rather than an arbitrary
+/// expression.
+///
+/// ```
+/// #[macro_use]
+/// extern crate drop_bomb;
+///
+/// fn main() {
+///     let mut bomb = drop_bomb::DropBomb::default();
+///     bomb.defuse();
+///     let x = defuse_guard_scope!(bomb, { 92 });
+///     assert_eq!(x, 92);
+///     assert!(bomb.is_defused());
+/// }
+/// ```
+#[macro_export]
+macro_rules! defuse_guard_scope {
+    ($bomb:ident $(. $field:ident)*, $body:block) => {{
+        $bomb $(. $field)*.arm();
+        let res = $body;
+        $bomb $(. $field)*.defuse();
+        res
+    }};
+}
+
+/// Globally enables or disables detonation of armed bombs, overriding
+/// `DROP_BOMB_DISABLE`.
+///
+/// The flag is advisory, so it is stored with `Ordering::Relaxed`:
+/// bombs dropped concurrently with the call may or may not see the new
+/// value. Bombs load it with `Ordering::Acquire`, so use
+/// `set_enabled_ordered` with `Release` if the switch must be ordered
+/// with other memory operations, for example around a shutdown barrier.
+pub fn set_enabled(enabled: bool) {
+    set_enabled_ordered(enabled, Ordering::Relaxed)
+}
+
+/// Like `set_enabled`, but stores the flag with the given `order`.
+///
+/// # Panics
+///
+/// Panics if `order` is `Acquire` or `AcqRel`, as `AtomicU8::store`
+/// does.
+pub fn set_enabled_ordered(enabled: bool, order: Ordering) {
+    global::set_enabled(enabled, order)
+}
+
+/// Whether detonation of armed bombs is globally enabled, see
+/// `set_enabled`. Like a bomb's drop, this uses `Ordering::Acquire`.
+pub fn is_enabled() -> bool {
+    global::enabled()
+}
+
+/// Sets whether `RuntimeBomb`s created from now on are real, overriding
+/// `DROP_BOMB_RUNTIME_CHECKS`. Existing bombs are unaffected.
+pub fn set_runtime_checks(checks: bool) {
+    global::set_runtime_checks(checks)
+}
+
+/// Returns the number of times an armed bomb was dropped and detonated
+/// (including dry-run bombs) since the start of the process.
+pub fn detonation_count() -> usize {
+    global::detonations()
+}
+
+/// Starts counting the detonations on the current thread, until the
+/// returned guard is dropped.
+///
+/// Unlike `detonation_count`, the count is not affected by bombs on
+/// other threads, such as other tests running in parallel. Scopes nest:
+/// an outer scope also counts the detonations of the inner ones.
+pub fn scoped_counter() -> CounterGuard {
+    CounterGuard {
+        outer: global::push_scoped_counter(),
+        _not_send: ::std::marker::PhantomData,
+    }
+}
+
+/// The guard returned by `scoped_counter`.
+#[must_use]
+pub struct CounterGuard {
+    outer: Option<usize>,
+    // The count lives in a thread-local.
+    _not_send: ::std::marker::PhantomData<*const ()>,
+}
+
+impl CounterGuard {
+    /// The number of detonations on this thread since the guard was
+    /// created.
+    pub fn count(&self) -> usize {
+        global::scoped_detonations()
+    }
+    /// Stops counting, returning the final count.
+    pub fn finish(self) -> usize {
+        self.count()
+    }
+}
+
+impl fmt::Debug for CounterGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CounterGuard")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+impl Drop for CounterGuard {
+    fn drop(&mut self) {
+        global::pop_scoped_counter(self.outer);
+    }
+}
+
+/// Returns the number of detonations of bombs with message `msg`.
+#[cfg(feature = "metrics")]
+pub fn detonation_count_for(msg: &str) -> usize {
+    global::detonations_for(msg)
+}
+
+/// Resets `detonation_count` and `detonation_count_for` to zero.
+#[cfg(feature = "metrics")]
+pub fn reset_detonation_counts() {
+    global::reset_detonations()
+}
+
+/// Resets all process-wide state to its defaults: detonation is
+/// enabled unless `DROP_BOMB_DISABLE=1` (the variable is read again),
+/// the default action is `Panic`, new `RuntimeBomb`s are real only if
+/// `DROP_BOMB_RUNTIME_CHECKS=1` (also read again), detonation output
+/// goes to stderr, and the detonation counts are zero. The current
+/// thread's deferred detonations are discarded.
+///
+/// Meant for test and fuzz harnesses which need each iteration to start
+/// from the same state. Bombs alive at the time of the call are still
+/// alive, so `assert_balanced` keeps counting them until they are
+/// dropped.
+pub fn reset_all_globals() {
+    global::reset_all()
+}
+
+/// Sets the action of bombs created from now on. Bombs which already
+/// exist keep their action, and `DropBomb::set_action` still overrides
+/// the default.
+pub fn set_default_action(action: DetonationAction) {
+    global::set_default_action(action)
+}
+
+/// Reports all detonations deferred on the current thread by bombs with
+/// `DetonationAction::Defer`, and aborts the process. Does nothing if
+/// there are none.
+pub fn flush_detonations() {
+    let pending = global::take_deferred_detonations();
+    if pending.is_empty() {
+        return;
+    }
+    global::report(format_args!("{} drop bomb(s) detonated:", pending.len()));
+    for msg in pending {
+        global::report(format_args!("{}", msg));
+    }
+    ::std::process::abort();
+}
+
+/// Removes and returns the detonations deferred on the current thread,
+/// without reporting them or aborting, for handlers which want to
+/// report them in their own way.
+pub fn drain_pending_detonations() -> Vec<String> {
+    global::take_deferred_detonations()
+}
+
+/// Redirects the output of bombs which print when they detonate (the
+/// `Abort`, `Warn` and `Defer` actions, and critical bombs) away from
+/// stderr, which is the default.
+///
+/// Panicking bombs are unaffected: their message goes to the panic
+/// hook.
+pub fn set_detonation_writer(writer: Box<dyn io::Write + Send + Sync>) {
+    global::set_writer(writer)
+}
+
+/// The messages of all armed bombs which are still alive, including
+/// leaked ones, oldest first.
+///
+/// Only `DropBomb`s and debug-build `DebugDropBomb`s are tracked.
+#[cfg(feature = "debug_registry")]
+pub fn live_bombs() -> Vec<String> {
+    global::live_messages()
+}
+
+/// Prints the messages of all live armed bombs, see `live_bombs`.
+#[cfg(feature = "debug_registry")]
+pub fn dump_live() {
+    let live = global::live_messages();
+    global::report(format_args!("{} live drop bomb(s):", live.len()));
+    for msg in live {
+        global::report(format_args!("{}", msg));
+    }
+}
+
+/// Whether the current thread is panicking because an armed bomb was
+/// dropped.
+///
+/// Detonation panics carry the message as a plain string payload, so
+/// that they print like ordinary panics. A panic hook can call this to
+/// tell them apart from other panics, for example to exit with a
+/// different code.
+pub fn is_detonating() -> bool {
+    global::detonating()
+}
+
+/// Panics if any bomb constructed so far has not been dropped.
+///
+/// A bomb which is `mem::forget`-ed (or leaked in a reference cycle)
+/// never runs its `drop`, so it can't detonate. Calling this at a point
+/// where all bombs are expected to be gone, for example at the end of a
+/// test, catches such leaks. `DebugDropBomb`s only count in debug
+/// builds.
+pub fn assert_balanced() {
+    let undropped = global::undropped();
+    if undropped != 0 {
+        panic!("{} drop bomb(s) were never dropped", undropped)
+    }
+}
+
+#[cfg(debug_assertions)]
+type DebugBomb = RealBomb;
+#[cfg(not(debug_assertions))]
+type DebugBomb = FakeBomb;
+
+struct RealBomb {
+    msg: Cow<'static, str>,
+    defused: bool,
+    sealed: bool,
+    detonate_during_panic: bool,
+    action: DetonationAction,
+    // The `Mutex` keeps the bomb `Sync` without requiring it of the
+    // hook. The hook is only reached through `&mut`, so it's never
+    // actually locked.
+    on_defuse: Option<Mutex<Box<dyn FnOnce() + Send>>>,
+    // Like `on_defuse`, behind a `Mutex` so that it needn't be `Sync`.
+    context: Option<Mutex<Box<dyn Any + Send>>>,
+    created_at: Option<&'static Location<'static>>,
+    help: Option<&'static str>,
+    #[cfg(feature = "track-defuse")]
+    defused_at: Option<&'static Location<'static>>,
+    #[cfg(feature = "debug_registry")]
+    id: u64,
+}
+
+impl RealBomb {
+    fn new(msg: Cow<'static, str>) -> RealBomb {
+        global::record_construction();
+        let res = RealBomb {
+            msg,
+            defused: false,
+            sealed: false,
+            detonate_during_panic: false,
+            action: global::default_action(),
+            on_defuse: None,
+            context: None,
+            created_at: None,
+            help: None,
+            #[cfg(feature = "track-defuse")]
+            defused_at: None,
+            #[cfg(feature = "debug_registry")]
+            id: global::next_id(),
+        };
+        res.sync_registry();
+        res
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    fn set_defused(&mut self, defused: bool) {
+        if !self.is_locked() {
+            let newly_defused = defused && !self.defused;
+            self.defused = defused;
+            #[cfg(feature = "track-defuse")]
+            {
+                self.defused_at = if defused {
+                    Some(Location::caller())
+                } else {
+                    None
+                };
+            }
+            self.sync_registry();
+            if newly_defused {
+                if let Some(f) = self.on_defuse.take() {
+                    f.into_inner().unwrap_or_else(PoisonError::into_inner)()
+                }
+            }
+        }
+    }
+    // Defuses the bomb because it is being consumed or built already
+    // defused, rather than because its obligation was fulfilled: the
+    // `on_defuse` hook doesn't run, and neither seals nor
+    // `track-defuse` apply.
+    fn disarm(&mut self) {
+        self.defused = true;
+        self.sync_registry();
+    }
+    // Keeps the bomb's entry in the registry of live bombs up to date.
+    fn sync_registry(&self) {
+        #[cfg(feature = "debug_registry")]
+        {
+            if self.defused {
+                global::unregister(self.id)
+            } else {
+                global::register(self.id, &self.msg)
+            }
+        }
+    }
+    fn seal(&mut self) {
+        self.sealed = true
+    }
+    // A sealed bomb can't be re-armed once defused.
+    fn is_locked(&self) -> bool {
+        self.sealed && self.defused
+    }
+    fn is_defused(&self) -> bool {
+        self.defused
+    }
+    fn message(&self) -> &str {
+        &self.msg
+    }
+    fn set_message(&mut self, msg: Cow<'static, str>) {
+        self.msg = msg;
+        self.sync_registry();
+    }
+    fn arm_with(&mut self, msg: Cow<'static, str>) {
+        if !self.is_locked() {
+            self.set_message(msg);
+            self.set_defused(false);
+        }
+    }
+    fn detonation_message(&self) -> String {
+        if let Some(help) = self.help {
+            let mut res = format!("note: {}\n", self.msg);
+            if let Some(loc) = self.created_at {
+                res.push_str(&format!("  --> {}\n", loc));
+            }
+            res.push_str(&format!("help: {}", help));
+            return res;
+        }
+        match self.created_at {
+            Some(loc) => format!("{} (bomb created at {})", self.msg, loc),
+            None => self.msg.to_string(),
+        }
+    }
+    fn take_message(&mut self) -> Cow<'static, str> {
+        mem::replace(&mut self.msg, Cow::Borrowed(""))
+    }
+    fn append_message(&mut self, suffix: &str) {
+        match self.msg {
+            Cow::Borrowed(msg) => {
+                // Leave room for more context: appends tend to come in
+                // runs, as each caller up the stack adds its own part.
+                let mut owned = String::with_capacity(2 * (msg.len() + suffix.len()));
+                owned.push_str(msg);
+                owned.push_str(suffix);
+                self.msg = Cow::Owned(owned);
+            }
+            Cow::Owned(ref mut msg) => msg.push_str(suffix),
+        }
+        self.sync_registry();
+    }
+    fn fmt_state(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.defused {
+            return f.write_str("armed");
+        }
+        f.write_str("defused")?;
+        #[cfg(feature = "track-defuse")]
+        {
+            if let Some(loc) = self.defused_at {
+                write!(f, " at {}:{}", loc.file(), loc.line())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RealBomb {
+    fn drop(&mut self) {
+        global::record_drop();
+        #[cfg(feature = "debug_registry")]
+        global::unregister(self.id);
+        if self.defused || !global::enabled() {
+            return;
+        }
+        if ::std::thread::panicking() {
+            if self.detonate_during_panic {
+                global::record_detonation(&self.msg);
+                global::report(format_args!("{}", self.detonation_message()));
+                ::std::process::abort();
+            }
+            return;
+        }
+        global::record_detonation(&self.msg);
+        match self.action {
+            DetonationAction::Panic => global::panic_detonation(&self.detonation_message()),
+            DetonationAction::Abort => {
+                global::report(format_args!("{}", self.detonation_message()));
+                ::std::process::abort();
+            }
+            DetonationAction::Warn => {
+                global::report(format_args!("warning: {}", self.detonation_message()));
+            }
+            DetonationAction::Defer => global::defer_detonation(self.detonation_message()),
+        }
+    }
+}
+
+#[cfg(not(debug_assertions))]
+struct FakeBomb {}
+
+#[cfg(not(debug_assertions))]
+impl FakeBomb {
+    fn new(_msg: Cow<'static, str>) -> FakeBomb {
+        FakeBomb {}
+    }
+    fn set_defused(&mut self, _defused: bool) {}
+    fn disarm(&mut self) {}
+    fn is_defused(&self) -> bool {
+        true
+    }
+    fn message(&self) -> &str {
+        ""
+    }
+    fn set_message(&mut self, _msg: Cow<'static, str>) {}
+    fn arm_with(&mut self, _msg: Cow<'static, str>) {}
+    fn detonation_message(&self) -> String {
+        String::new()
+    }
+    fn take_message(&mut self) -> Cow<'static, str> {
+        Cow::Borrowed("")
+    }
+    fn seal(&mut self) {}
+    fn append_message(&mut self, _suffix: &str) {}
+    fn fmt_state(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("defused")
+    }
+}
+
+#[cfg(not(debug_assertions))]
+impl Drop for FakeBomb {
+    fn drop(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    const CHILD_ENV: &str = "DROP_BOMB_TEST_CHILD";
+
+    fn is_child() -> bool {
+        env::var_os(CHILD_ENV).is_some()
+    }
+
+    fn panic_message(f: impl FnOnce()) -> String {
+        let payload = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(f)).unwrap_err();
+        match payload.downcast::<String>() {
+            Ok(msg) => *msg,
+            Err(payload) => payload.downcast_ref::<&str>().unwrap().to_string(),
+        }
+    }
+
+    // Runs the test `name` in a fresh copy of the test binary, with
+    // `is_child` returning true. Useful for tests which abort or touch
+    // process-wide state. Miri can't spawn processes, so such tests are
+    // marked `#[cfg_attr(miri, ignore)]`.
+    fn run_child(name: &str, envs: &[(&str, &str)]) -> process::Output {
+        process::Command::new(env::current_exe().unwrap())
+            .args(["--exact", name, "--nocapture", "--test-threads=1"])
+            .env(CHILD_ENV, "1")
+            .envs(envs.iter().cloned())
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    #[should_panic(expected = "Kaboom")]
+    fn armed_bomb_bombs() {
+        let _b = DropBomb::new("Kaboom");
+    }
+
+    #[test]
+    fn defused_bomb_is_safe() {
+        let mut b = DropBomb::new("Kaboom");
+        assert!(!b.is_defused());
+        b.defuse();
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    #[should_panic(expected = r#"printf("sucks to be you"); exit(666);"#)]
+    fn no_double_panics() {
+        let _b = DropBomb::new("Kaboom");
+        panic!(r#"printf("sucks to be you"); exit(666);"#)
+    }
+
+    #[test]
+    #[should_panic(expected = "Kaboom")]
+    #[cfg(debug_assertions)]
+    fn debug_bomb_bombs_if_debug() {
+        let _b = DebugDropBomb::new("Kaboom");
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn debug_bomb_bombs_if_debug() {
+        let _b = DebugDropBomb::new("Kaboom");
+    }
+
+    #[test]
+    fn defused_bomb_is_safe_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        assert!(!b.is_defused());
+        #[cfg(not(debug_assertions))]
+        assert!(b.is_defused());
+        b.defuse();
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    #[should_panic(expected = r#"printf("sucks to be you"); exit(666);"#)]
+    fn no_double_panics_if_debug() {
+        let _b = DebugDropBomb::new("Kaboom");
+        panic!(r#"printf("sucks to be you"); exit(666);"#)
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn debug_bomb_is_zst() {
+        assert_eq!(::std::mem::size_of::<DebugDropBomb>(), 0);
+    }
+
+    #[test]
+    fn defuse_pair_defuses_both() {
+        let mut a = DropBomb::new("Kaboom");
+        let mut b = DropBomb::new("Kaboom");
+        defuse_pair(&mut a, &mut b);
+        assert!(a.is_defused());
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn defuse_pair_on_ok_defuses_both_on_ok() {
+        let mut a = DropBomb::new("Kaboom");
+        let mut b = DropBomb::new("Kaboom");
+        let r: Result<i32, ()> = defuse_pair_on_ok(&mut a, &mut b, Ok(92));
+        assert_eq!(r, Ok(92));
+        assert!(a.is_defused());
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn defuse_pair_on_ok_keeps_both_armed_on_err() {
+        let mut a = DropBomb::new("Kaboom");
+        let mut b = DropBomb::new("Kaboom");
+        let r: Result<(), &str> = defuse_pair_on_ok(&mut a, &mut b, Err("oops"));
+        assert_eq!(r, Err("oops"));
+        assert!(!a.is_defused());
+        assert!(!b.is_defused());
+        defuse_pair(&mut a, &mut b);
+    }
+
+    #[test]
+    fn debug_does_not_detonate() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(
+            format!("{:?}", b),
+            r#"DropBomb { message: "Kaboom", defused: false }"#
+        );
+        assert!(!b.is_defused());
+        b.defuse();
+        assert_eq!(
+            format!("{:?}", b),
+            r#"DropBomb { message: "Kaboom", defused: true }"#
+        );
+    }
+
+    #[test]
+    fn debug_does_not_detonate_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            format!("{:?}", b),
+            r#"DebugDropBomb { message: "Kaboom", defused: false }"#
+        );
+        #[cfg(not(debug_assertions))]
+        assert_eq!(
+            format!("{:?}", b),
+            r#"DebugDropBomb { message: "", defused: true }"#
+        );
+        b.defuse();
+    }
+
+    #[test]
+    #[should_panic(expected = "Second kaboom")]
+    fn arm_with_rearms_with_new_message() {
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        b.arm_with(format!("Second {}", "kaboom"));
+        assert!(!b.is_defused());
+    }
+
+    #[test]
+    #[should_panic(expected = "Second kaboom")]
+    #[cfg(debug_assertions)]
+    fn arm_with_rearms_with_new_message_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        b.defuse();
+        b.arm_with("Second kaboom");
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn arm_with_rearms_with_new_message_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        b.defuse();
+        b.arm_with("Second kaboom");
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn critical_bomb_aborts_during_panic() {
+        if is_child() {
+            let mut b = DropBomb::new("Critical kaboom");
+            b.set_detonate_during_panic(true);
+            panic!("unrelated panic");
+        }
+        let output = run_child("tests::critical_bomb_aborts_during_panic", &[]);
+        assert!(!output.status.success());
+        assert_ne!(output.status.code(), Some(101));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Critical kaboom"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn default_bomb_is_silent_during_panic() {
+        if is_child() {
+            let _b = DropBomb::new("Silent kaboom");
+            panic!("unrelated panic");
+        }
+        let output = run_child("tests::default_bomb_is_silent_during_panic", &[]);
+        assert_eq!(output.status.code(), Some(101));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("unrelated panic"));
+        assert!(!stderr.contains("Silent kaboom"));
+    }
+
+    #[test]
+    fn detonation_message_matches_panic() {
+        let bombs = vec![
+            DropBomb::new("Kaboom"),
+            DropBomb::new(format!("Kaboom #{}", 92)),
+            {
+                let mut b = DropBomb::new("Kaboom");
+                b.defuse();
+                b.arm_with("Second kaboom");
+                b
+            },
+        ];
+        for b in bombs {
+            let expected = b.detonation_message();
+            assert_eq!(panic_message(move || drop(b)), expected);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn detonation_message_matches_panic_if_debug() {
+        let b = DebugDropBomb::new("Kaboom");
+        let expected = b.detonation_message();
+        assert_eq!(panic_message(move || drop(b)), expected);
+    }
+
+    #[test]
+    fn as_option() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.as_option(), Some("Kaboom"));
+        b.defuse();
+        assert_eq!(b.as_option(), None);
+    }
+
+    #[test]
+    fn as_option_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        assert_eq!(b.as_option(), Some("Kaboom"));
+        b.defuse();
+        assert_eq!(b.as_option(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "value must be explicitly consumed before being dropped")]
+    fn default_bomb_bombs() {
+        let _b = DropBomb::default();
+    }
+
+    #[test]
+    #[should_panic(expected = "value must be explicitly consumed before being dropped")]
+    #[cfg(debug_assertions)]
+    fn default_bomb_bombs_if_debug() {
+        let _b = DebugDropBomb::default();
+    }
+
+    #[test]
+    fn try_new_accepts_message() {
+        let mut b = DropBomb::try_new("Kaboom").unwrap();
+        assert!(!b.is_defused());
+        b.defuse();
+        let mut b = DebugDropBomb::try_new("Kaboom").unwrap();
+        b.defuse();
+    }
+
+    #[test]
+    fn try_new_rejects_empty_message() {
+        assert_eq!(DropBomb::try_new("").unwrap_err(), EmptyMessage);
+        assert_eq!(
+            DropBomb::try_new(" \t\n".to_string()).unwrap_err(),
+            EmptyMessage
+        );
+        assert_eq!(DebugDropBomb::try_new("  ").unwrap_err(), EmptyMessage);
+    }
+
+    #[test]
+    fn guard_all_defuses_on_normal_exit() {
+        let mut bombs = vec![DropBomb::new("Kaboom"), DropBomb::new("Kaboom")];
+        {
+            let mut guard = guard_all(&mut bombs);
+            guard.push(DropBomb::new("Kaboom"));
+            assert!(guard.iter().all(|b| !b.is_defused()));
+        }
+        assert_eq!(bombs.len(), 3);
+        assert!(bombs.iter().all(DropBomb::is_defused));
+    }
+
+    #[test]
+    fn guard_all_keeps_bombs_armed_on_panic() {
+        let mut bombs = vec![DropBomb::new("Kaboom"), DropBomb::new("Kaboom")];
+        let res = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            let _guard = guard_all(&mut bombs);
+            panic!("oops");
+        }));
+        assert!(res.is_err());
+        assert!(bombs.iter().all(|b| !b.is_defused()));
+        bombs.iter_mut().for_each(DropBomb::defuse);
+    }
+
+    #[test]
+    fn phantom_bomb_is_zst() {
+        assert_eq!(::std::mem::size_of::<PhantomBomb>(), 0);
+        let mut b = PhantomBomb::new("Kaboom");
+        assert!(b.is_defused());
+        b.defuse();
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    #[should_panic(expected = "Updated kaboom")]
+    fn set_message_if_armed_updates_armed_bomb() {
+        let mut b = DropBomb::new("Kaboom");
+        assert!(b.set_message_if_armed("Updated kaboom"));
+        assert_eq!(b.as_option(), Some("Updated kaboom"));
+    }
+
+    #[test]
+    fn set_message_if_armed_ignores_defused_bomb() {
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        assert!(!b.set_message_if_armed("Updated kaboom"));
+        assert_eq!(b.detonation_message(), "Kaboom");
+    }
+
+    #[test]
+    fn set_message_if_armed_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        {
+            assert!(b.set_message_if_armed("Updated kaboom"));
+            assert_eq!(b.as_option(), Some("Updated kaboom"));
+        }
+        b.defuse();
+        assert!(!b.set_message_if_armed("Kaboom"));
+    }
+
+    #[test]
+    fn merge() {
+        for &(a_defused, b_defused) in &[(false, false), (false, true), (true, false), (true, true)]
+        {
+            let mut a = DropBomb::new("First kaboom");
+            a.set_defused(a_defused);
+            let mut b = DropBomb::new("Second kaboom");
+            b.set_defused(b_defused);
+            let mut merged = a.merge(b);
+            assert_eq!(merged.is_defused(), a_defused && b_defused);
+            assert_eq!(merged.detonation_message(), "First kaboom\nSecond kaboom");
+            merged.defuse();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "First kaboom\nSecond kaboom")]
+    fn merged_bomb_bombs() {
+        let mut a = DropBomb::new("First kaboom");
+        a.defuse();
+        let _merged = a.merge(DropBomb::new("Second kaboom"));
+    }
+
+    #[test]
+    fn merge_if_debug() {
+        let mut a = DebugDropBomb::new("First kaboom");
+        a.defuse();
+        let mut merged = a.merge(DebugDropBomb::new("Second kaboom"));
+        #[cfg(debug_assertions)]
+        assert!(!merged.is_defused());
+        merged.defuse();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Kaboom")]
+    fn downgraded_bomb_bombs_if_debug() {
+        let b = DropBomb::new("Kaboom");
+        let _b = b.downgrade();
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn downgraded_bomb_bombs_if_debug() {
+        let b = DropBomb::new("Kaboom");
+        let b = b.downgrade();
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn downgrade_keeps_defused_state() {
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        let b = b.downgrade();
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn env_var_disables_detonation() {
+        if is_child() {
+            let _b = DropBomb::new("Kaboom");
+            return;
+        }
+        let name = "tests::env_var_disables_detonation";
+        assert!(run_child(name, &[("DROP_BOMB_DISABLE", "1")])
+            .status
+            .success());
+        assert!(!run_child(name, &[("DROP_BOMB_DISABLE", "0")])
+            .status
+            .success());
+        assert!(!run_child(name, &[]).status.success());
+    }
+
+    #[test]
+    fn into_parts_does_not_detonate() {
+        let b = DropBomb::new(format!("Kaboom #{}", 92));
+        let (msg, defused) = b.into_parts();
+        assert_eq!(msg, "Kaboom #92");
+        assert!(!defused);
+    }
+
+    #[test]
+    #[should_panic(expected = "Kaboom")]
+    fn from_parts_round_trip() {
+        let (msg, defused) = DropBomb::new("Kaboom").into_parts();
+        let mut b = DropBomb::from_parts(msg, true);
+        assert!(b.is_defused());
+        let (msg, _) = b.into_parts();
+        b = DropBomb::from_parts(msg, defused);
+        assert!(!b.is_defused());
+    }
+
+    #[test]
+    fn into_parts_if_debug() {
+        let (msg, defused) = DebugDropBomb::new("Kaboom").into_parts();
+        #[cfg(debug_assertions)]
+        assert_eq!((msg, defused), (Cow::Borrowed("Kaboom"), false));
+        #[cfg(not(debug_assertions))]
+        assert_eq!((msg, defused), (Cow::Borrowed(""), true));
+        let b = DebugDropBomb::from_parts("Kaboom".into(), true);
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn sealed_bomb_stays_defused() {
+        let mut b = DropBomb::new("Kaboom");
+        b.seal();
+        b.arm();
+        assert!(!b.is_defused());
+        b.defuse();
+        b.arm();
+        b.set_defused(false);
+        b.arm_with("Second kaboom");
+        assert!(b.is_defused());
+        assert_eq!(b.detonation_message(), "Kaboom");
+    }
+
+    #[test]
+    fn sealed_bomb_stays_defused_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        b.defuse();
+        b.seal();
+        b.arm();
+        b.arm_with("Second kaboom");
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    #[should_panic(expected = "Kaboom")]
+    fn arm_rearms() {
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        b.arm();
+    }
+
+    #[test]
+    fn message_or() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.message(), "Kaboom");
+        assert_eq!(b.message_or("<no message>"), "Kaboom");
+        b.defuse();
+        let mut b = DropBomb::new("");
+        assert_eq!(b.message_or("<no message>"), "<no message>");
+        b.defuse();
+    }
+
+    #[test]
+    fn message_or_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        assert_eq!(b.message_or("<no message>"), "Kaboom");
+        #[cfg(not(debug_assertions))]
+        assert_eq!(b.message_or("<no message>"), "<no message>");
+        b.defuse();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn forgotten_bomb_is_unbalanced() {
+        if is_child() {
+            mem::forget(DropBomb::new("Kaboom"));
+            assert!(panic_message(assert_balanced).contains("were never dropped"));
+            return;
+        }
+        let output = run_child("tests::forgotten_bomb_is_unbalanced", &[]);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn dropped_bombs_are_balanced() {
+        if is_child() {
+            let mut b = DropBomb::new("Kaboom");
+            let a = AtomicDropBomb::new("Kaboom");
+            assert_balanced_fails();
+            b.defuse();
+            a.defuse();
+            drop((a, b));
+            let _ = panic_message(|| drop(DropBomb::new("Kaboom")));
+            assert_balanced();
+            return;
+        }
+        let output = run_child("tests::dropped_bombs_are_balanced", &[]);
+        assert!(output.status.success());
+    }
+
+    fn assert_balanced_fails() {
+        assert!(::std::panic::catch_unwind(assert_balanced).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Kaboom: while closing: file.txt")]
+    fn append_message() {
+        let mut b = DropBomb::new("Kaboom");
+        b.append_message(": while closing");
+        b.append_message(": ");
+        b.append_message("file.txt");
+        assert_eq!(b.message(), "Kaboom: while closing: file.txt");
+    }
+
+    #[test]
+    fn defuse_array_defuses_all() {
+        let mut bombs = [
+            DropBomb::new("Kaboom"),
+            DropBomb::new("Kaboom"),
+            DropBomb::new("Kaboom"),
+        ];
+        defuse_array(&mut bombs);
+        assert!(bombs.iter().all(DropBomb::is_defused));
+    }
+
+    #[test]
+    fn defuse_returning() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.defuse_returning(92), 92);
+        assert!(b.is_defused());
+        let mut b = DebugDropBomb::new("Kaboom");
+        assert_eq!(b.defuse_returning("value"), "value");
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn manually_drop_bomb_does_not_detonate() {
+        let b = mem::ManuallyDrop::new(DropBomb::new("Kaboom"));
+        assert!(!b.is_defused());
+        let mut b = mem::ManuallyDrop::into_inner(b);
+        b.defuse();
+    }
+
+    #[test]
+    fn cow_messages() {
+        let mut b = DropBomb::new_cow(Cow::Borrowed("Kaboom"));
+        assert_eq!(b.message(), "Kaboom");
+        b.set_message_cow(Cow::Owned("Second kaboom".to_string()));
+        assert_eq!(b.message(), "Second kaboom");
+        b.set_message("Third kaboom");
+        assert_eq!(b.message(), "Third kaboom");
+        b.defuse();
+
+        let mut b = DebugDropBomb::new_cow(Cow::Owned("Kaboom".to_string()));
+        b.set_message_cow(Cow::Borrowed("Second kaboom"));
+        #[cfg(debug_assertions)]
+        assert_eq!(b.message(), "Second kaboom");
+        b.defuse();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn set_enabled_toggles_detonation() {
+        if is_child() {
+            set_enabled(false);
+            drop(DropBomb::new("Kaboom"));
+            set_enabled_ordered(true, Ordering::SeqCst);
+            assert_eq!(panic_message(|| drop(DropBomb::new("Kaboom"))), "Kaboom");
+            set_enabled_ordered(false, Ordering::Release);
+            drop(DropBomb::new("Kaboom"));
+            return;
+        }
+        let output = run_child("tests::set_enabled_toggles_detonation", &[]);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn set_enabled_ordered_publishes_prior_writes() {
+        if is_child() {
+            use std::sync::atomic::AtomicUsize;
+
+            static DATA: AtomicUsize = AtomicUsize::new(0);
+            set_enabled(true);
+            let t = ::std::thread::spawn(|| {
+                DATA.store(92, Ordering::Relaxed);
+                set_enabled_ordered(false, Ordering::Release);
+            });
+            while is_enabled() {
+                ::std::hint::spin_loop();
+            }
+            assert_eq!(DATA.load(Ordering::Relaxed), 92);
+            drop(DropBomb::new("Kaboom"));
+            t.join().unwrap();
+            return;
+        }
+        let output = run_child("tests::set_enabled_ordered_publishes_prior_writes", &[]);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn set_enabled_overrides_env_var() {
+        if is_child() {
+            set_enabled(true);
+            let _b = DropBomb::new("Kaboom");
+            return;
+        }
+        let output = run_child(
+            "tests::set_enabled_overrides_env_var",
+            &[("DROP_BOMB_DISABLE", "1")],
+        );
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn dry_run_bomb_does_not_panic() {
+        let before = detonation_count();
+        drop(DropBomb::new_dry_run("Dry kaboom"));
+        assert!(detonation_count() > before);
+    }
+
+    #[test]
+    #[should_panic(expected = "Dry kaboom")]
+    fn promoted_dry_run_bomb_bombs() {
+        let mut b = DropBomb::new_dry_run("Dry kaboom");
+        b.promote();
+    }
+
+    #[test]
+    fn detonation_count_counts_panics() {
+        let before = detonation_count();
+        let _ = panic_message(|| drop(DropBomb::new("Kaboom")));
+        assert!(detonation_count() > before);
+    }
+
+    #[test]
+    #[should_panic(expected = "Kaboom #92")]
+    fn with_message_owned() {
+        let b = DropBomb::with_message_owned(format!("Kaboom #{}", 92));
+        assert_eq!(b.detonation_message(), "Kaboom #92");
+        let mut d = DebugDropBomb::with_message_owned(format!("Kaboom #{}", 92));
+        d.defuse();
+    }
+
+    #[test]
+    fn defuse_once() {
+        let mut b = DropBomb::new("Kaboom");
+        assert!(b.defuse_once());
+        assert!(!b.defuse_once());
+        b.arm();
+        assert!(b.defuse_once());
+
+        let mut b = DebugDropBomb::new("Kaboom");
+        assert_eq!(b.defuse_once(), cfg!(debug_assertions));
+        assert!(!b.defuse_once());
+    }
+
+    #[test]
+    fn armed_message() {
+        let mut bombs = [DropBomb::new("First"), DropBomb::new("Second")];
+        assert_eq!(bombs[0].armed_message(), Some("First"));
+        bombs[0].defuse();
+        assert_eq!(bombs[0].armed_message(), None);
+        let outstanding: Vec<&str> = bombs.iter().filter_map(DropBomb::armed_message).collect();
+        assert_eq!(outstanding, ["Second"]);
+        bombs[1].defuse();
+    }
+
+    #[test]
+    fn armed_message_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        assert_eq!(b.armed_message(), Some("Kaboom"));
+        b.defuse();
+        assert_eq!(b.armed_message(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Kaboom")]
+    fn panic_immediately_if_armed_panics() {
+        let b = DropBomb::new("Kaboom");
+        b.panic_immediately_if_armed();
+    }
+
+    #[test]
+    fn panic_immediately_if_armed_passes_when_defused() {
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        b.panic_immediately_if_armed();
+        assert!(b.is_defused());
+        let mut b = DebugDropBomb::new("Kaboom");
+        b.defuse();
+        b.panic_immediately_if_armed();
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn detonation_count_for_groups_by_message() {
+        if is_child() {
+            reset_detonation_counts();
+            for msg in &["connection leaked", "file leaked", "connection leaked"] {
+                drop(DropBomb::new_dry_run(*msg));
+            }
+            assert_eq!(detonation_count_for("connection leaked"), 2);
+            assert_eq!(detonation_count_for("file leaked"), 1);
+            assert_eq!(detonation_count_for("socket leaked"), 0);
+            assert_eq!(detonation_count(), 3);
+            reset_detonation_counts();
+            assert_eq!(detonation_count_for("connection leaked"), 0);
+            assert_eq!(detonation_count(), 0);
+            return;
+        }
+        let output = run_child("tests::detonation_count_for_groups_by_message", &[]);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn abort_action_aborts() {
+        if is_child() {
+            let mut b = DropBomb::new("Abort kaboom");
+            b.set_action(DetonationAction::Abort);
+            return;
+        }
+        let output = run_child("tests::abort_action_aborts", &[]);
+        assert!(!output.status.success());
+        assert_ne!(output.status.code(), Some(101));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Abort kaboom"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn set_default_action_applies_to_new_bombs() {
+        if is_child() {
+            let mut old = DropBomb::new("Old kaboom");
+            set_default_action(DetonationAction::Warn);
+            drop(DropBomb::new("Warn kaboom"));
+            let mut b = DropBomb::new("Kaboom");
+            b.set_action(DetonationAction::Panic);
+            assert_eq!(panic_message(|| drop(b)), "Kaboom");
+            old.set_action(DetonationAction::Warn);
+            return;
+        }
+        let output = run_child("tests::set_default_action_applies_to_new_bombs", &[]);
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("warning: Warn kaboom"));
+    }
+
+    #[test]
+    fn action_reflects_set_action() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.action(), DetonationAction::Panic);
+        b.set_action(DetonationAction::Abort);
+        assert_eq!(b.action(), DetonationAction::Abort);
+        b.defuse();
+        let mut b = DropBomb::new_dry_run("Kaboom");
+        assert_eq!(b.action(), DetonationAction::Warn);
+        b.promote();
+        assert_eq!(b.action(), DetonationAction::Panic);
+        b.defuse();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn action_reflects_default_action() {
+        if is_child() {
+            set_default_action(DetonationAction::Abort);
+            let mut b = DropBomb::new("Kaboom");
+            assert_eq!(b.action(), DetonationAction::Abort);
+            b.set_action(DetonationAction::Warn);
+            assert_eq!(b.action(), DetonationAction::Warn);
+            b.defuse();
+            return;
+        }
+        let output = run_child("tests::action_reflects_default_action", &[]);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    fn run_defuses_on_ok() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.run(|| Ok::<i32, ()>(92)), Ok(92));
+        assert!(b.is_defused());
+        let mut b = DebugDropBomb::new("Kaboom");
+        assert_eq!(b.run(|| Ok::<i32, ()>(92)), Ok(92));
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn run_keeps_bomb_armed_on_err() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.run(|| Err::<(), _>("oops")), Err("oops"));
+        assert!(!b.is_defused());
+        b.defuse();
+    }
+
+    #[test]
+    fn run_keeps_bomb_armed_on_panic() {
+        let mut b = DropBomb::new("Kaboom");
+        let res = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            b.run(|| -> Result<(), ()> { panic!("oops") })
+        }));
+        assert!(res.is_err());
+        assert!(!b.is_defused());
+        b.defuse();
+    }
+
+    #[test]
+    fn message_len() {
+        let mut b = DropBomb::new("Kaboom ☢");
+        assert_eq!(b.message_len(), b.message().len());
+        assert_eq!(b.message_len(), 10);
+        b.defuse();
+        let mut b = DebugDropBomb::new("Kaboom");
+        assert_eq!(b.message_len(), if cfg!(debug_assertions) { 6 } else { 0 });
+        b.defuse();
+    }
+
+    #[test]
+    fn flush_detonations_without_pending_does_nothing() {
+        let mut b = DropBomb::new("Kaboom");
+        b.set_action(DetonationAction::Defer);
+        b.defuse();
+        drop(b);
+        flush_detonations();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn flush_detonations_reports_all() {
+        if is_child() {
+            for msg in &["First kaboom", "Second kaboom"] {
+                let mut b = DropBomb::new(*msg);
+                b.set_action(DetonationAction::Defer);
+            }
+            eprintln!("both dropped");
+            flush_detonations();
+            return;
+        }
+        let output = run_child("tests::flush_detonations_reports_all", &[]);
+        assert!(!output.status.success());
+        assert_ne!(output.status.code(), Some(101));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let flushed = &stderr[stderr.find("both dropped").unwrap()..];
+        assert!(flushed.contains("2 drop bomb(s) detonated:\nFirst kaboom\nSecond kaboom\n"));
+    }
+
+    #[test]
+    fn clone_disarmed() {
+        let mut template = DropBomb::new("Kaboom");
+        template.set_action(DetonationAction::Warn);
+        let mut b = template.clone_disarmed();
+        assert!(b.is_defused());
+        assert_eq!(b.message(), "Kaboom");
+        assert_eq!(b.action(), DetonationAction::Warn);
+        assert!(!template.is_defused());
+        b.arm();
+        b.defuse();
+        template.defuse();
+
+        let mut template = DropBomb::with_help("Kaboom", "defuse it");
+        let mut b = template.clone_disarmed();
+        assert!(b.is_defused());
+        assert_eq!(b.detonation_message(), template.detonation_message());
+        assert!(b.detonation_message().contains("  --> "));
+        b.defuse();
+        template.defuse();
+    }
+
+    #[test]
+    fn display() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.to_string(), r#"DropBomb(armed): "Kaboom""#);
+        b.defuse();
+        #[cfg(not(feature = "track-defuse"))]
+        assert_eq!(b.to_string(), r#"DropBomb(defused): "Kaboom""#);
+        #[cfg(feature = "track-defuse")]
+        {
+            let line = line!() - 5;
+            assert_eq!(
+                b.to_string(),
+                format!(r#"DropBomb(defused at {}:{}): "Kaboom""#, file!(), line)
+            );
+            b.arm();
+            assert_eq!(b.to_string(), r#"DropBomb(armed): "Kaboom""#);
+            b.defuse();
+        }
+    }
+
+    #[test]
+    fn display_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        assert_eq!(b.to_string(), r#"DebugDropBomb(armed): "Kaboom""#);
+        #[cfg(not(debug_assertions))]
+        assert_eq!(b.to_string(), r#"DebugDropBomb(defused): """#);
+        b.defuse();
+    }
+
+    #[test]
+    fn arm_all_rearms_every_bomb() {
+        let mut bombs = vec![DropBomb::new("Kaboom"), DropBomb::new("Kaboom")];
+        bombs.iter_mut().for_each(DropBomb::defuse);
+        arm_all(&mut bombs);
+        for b in bombs {
+            assert_eq!(panic_message(move || drop(b)), "Kaboom");
+        }
+    }
+
+    #[test]
+    fn defuse_if_ok() {
+        let mut b = DropBomb::new("Kaboom");
+        let err: Result<(), &str> = Err("oops");
+        b.defuse_if_ok(&err);
+        assert!(!b.is_defused());
+        let ok: Result<i32, ()> = Ok(92);
+        b.defuse_if_ok(&ok);
+        assert!(b.is_defused());
+        assert_eq!(ok, Ok(92));
+
+        let mut b = DebugDropBomb::new("Kaboom");
+        b.defuse_if_ok(&err);
+        assert_eq!(b.is_defused(), cfg!(not(debug_assertions)));
+        b.defuse_if_ok(&ok);
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn detonation_writer_receives_warnings() {
+        use std::io::{self, Write};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for SharedBuf {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        if is_child() {
+            let buf = SharedBuf::default();
+            set_detonation_writer(Box::new(buf.clone()));
+            drop(DropBomb::new_dry_run("Written kaboom"));
+            let written = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+            assert!(written.contains("warning: Written kaboom\n"));
+            return;
+        }
+        let output = run_child("tests::detonation_writer_receives_warnings", &[]);
+        assert!(output.status.success());
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("Written kaboom"));
+    }
+
+    #[test]
+    fn is_zero_sized_matches_the_profile() {
+        const { assert!(!DropBomb::IS_ZERO_SIZED) };
+        #[cfg(debug_assertions)]
+        const {
+            assert!(!DebugDropBomb::IS_ZERO_SIZED)
+        };
+        #[cfg(not(debug_assertions))]
+        const {
+            assert!(DebugDropBomb::IS_ZERO_SIZED)
+        };
+    }
+
+    #[test]
+    fn retain_armed_removes_only_defused() {
+        let mut bombs: Vec<DropBomb> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|&it| DropBomb::new(it))
+            .collect();
+        bombs[0].defuse();
+        bombs[2].defuse();
+        retain_armed(&mut bombs);
+        let left: Vec<&str> = bombs.iter().map(DropBomb::message).collect();
+        assert_eq!(left, ["b", "d"]);
+        bombs.iter_mut().for_each(DropBomb::defuse);
+        retain_armed(&mut bombs);
+        assert!(bombs.is_empty());
+    }
+
+    #[test]
+    fn on_defuse_runs_once_per_cycle() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let hook = |count: &Arc<AtomicUsize>| {
+            let count = Arc::clone(count);
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        let mut b = DropBomb::new("Kaboom");
+        b.set_on_defuse(hook(&count));
+        b.defuse();
+        b.defuse();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        b.arm();
+        b.defuse();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        b.arm();
+        b.set_on_defuse(hook(&count));
+        b.defuse();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn replace_action_returns_previous() {
+        let mut b = DropBomb::new("Kaboom");
+        b.set_action(DetonationAction::Panic);
+        assert_eq!(
+            b.replace_action(DetonationAction::Warn),
+            DetonationAction::Panic
+        );
+        assert_eq!(b.action(), DetonationAction::Warn);
+        assert_eq!(
+            b.replace_action(DetonationAction::Panic),
+            DetonationAction::Warn
+        );
+        b.defuse();
+    }
+
+    #[test]
+    fn boxed_bombs_of_both_kinds() {
+        let mut bombs: Vec<Box<dyn Bomb>> =
+            vec![new_boxed("real", false), new_boxed("debug", true)];
+        assert_eq!(bombs[0].message(), "real");
+        assert!(!bombs[0].is_defused());
+        #[cfg(debug_assertions)]
+        assert_eq!(bombs[1].message(), "debug");
+        bombs.iter_mut().for_each(|it| it.defuse());
+        assert!(bombs.iter().all(|it| it.is_defused()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Kaboom")]
+    fn armed_boxed_bomb_bombs() {
+        let _b = new_boxed("Kaboom", false);
+    }
+
+    #[test]
+    fn defuse_guard_scope_defuses_on_normal_exit() {
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        let x = defuse_guard_scope!(b, {
+            assert!(!b.is_defused());
+            92
+        });
+        assert_eq!(x, 92);
+        assert!(b.is_defused());
+
+        struct File {
+            bomb: DropBomb,
+        }
+        let mut file = File { bomb: b };
+        defuse_guard_scope!(file.bomb, { assert!(!file.bomb.is_defused()) });
+        assert!(file.bomb.is_defused());
+    }
+
+    #[test]
+    fn defuse_guard_scope_stays_armed_on_early_exit() {
+        fn fail() -> Result<(), ()> {
+            Err(())
+        }
+        fn boom() {
+            panic!("inner")
+        }
+        fn fallible(b: &mut DropBomb) -> Result<(), ()> {
+            defuse_guard_scope!(b, { fail()? });
+            Ok(())
+        }
+        let mut b = DropBomb::new("Kaboom");
+        assert!(fallible(&mut b).is_err());
+        assert!(!b.is_defused());
+
+        let res = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            defuse_guard_scope!(b, { boom() })
+        }));
+        assert!(res.is_err());
+        assert!(!b.is_defused());
+        b.defuse();
+    }
+
+    #[test]
+    fn message_eq_compares_messages() {
+        let mut b = DropBomb::new("Kaboom");
+        assert!(b.message_eq("Kaboom"));
+        assert!(!b.message_eq("Boom"));
+        b.defuse();
+
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        assert!(b.message_eq("Kaboom"));
+        #[cfg(not(debug_assertions))]
+        assert!(b.message_eq(""));
+        assert!(!b.message_eq("Boom"));
+        b.defuse();
+    }
+
+    #[test]
+    fn report_lists_armed_bombs() {
+        let mut a = DropBomb::new("a");
+        let mut b = DropBomb::new("b");
+        let mut c = DropBomb::new("c");
+        b.defuse();
+        assert_eq!(
+            report(&[&a, &b, &c]),
+            "2 outstanding obligations:\n - a\n - c"
+        );
+        a.defuse();
+        c.defuse();
+        assert_eq!(report(&[&a, &b, &c]), "all clear");
+        assert_eq!(report(&[]), "all clear");
+    }
+
+    #[test]
+    fn into_debug_and_into_real_round_trip() {
+        let b = DropBomb::new("Kaboom");
+        let b = b.into_debug();
+        #[cfg(debug_assertions)]
+        {
+            assert!(!b.is_defused());
+            assert_eq!(b.message(), "Kaboom");
+        }
+        let mut b = b.into_real();
+        #[cfg(debug_assertions)]
+        {
+            assert!(!b.is_defused());
+            assert_eq!(b.message(), "Kaboom");
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            assert!(b.is_defused());
+            assert_eq!(b.message(), "");
+        }
+        b.defuse();
+
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        let b = b.into_debug().into_real();
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Kaboom")]
+    fn into_real_bomb_bombs() {
+        let _b = DebugDropBomb::new("Kaboom").into_real();
+    }
+
+    #[test]
+    #[cfg(feature = "debug_registry")]
+    fn forgotten_bomb_is_live() {
+        let b = DropBomb::new("Forgotten kaboom");
+        let mut d = DropBomb::new("Defused kaboom");
+        assert!(live_bombs().iter().any(|it| it == "Defused kaboom"));
+        d.defuse();
+        mem::forget(b);
+        let live = live_bombs();
+        assert!(live.iter().any(|it| it == "Forgotten kaboom"));
+        assert!(!live.iter().any(|it| it == "Defused kaboom"));
+        dump_live();
+    }
+
+    #[test]
+    fn defuse_with_sibling_fields_borrowed() {
+        struct Conn {
+            buf: Vec<u8>,
+            bomb: DropBomb,
+        }
+        let mut conn = Conn {
+            buf: Vec::new(),
+            bomb: DropBomb::new("Kaboom"),
+        };
+        let buf = &mut conn.buf;
+        conn.bomb.defuse();
+        buf.push(92);
+        assert!(conn.bomb.is_defused());
+        assert_eq!(conn.buf, [92]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn panic_hook_detects_detonations() {
+        if is_child() {
+            use std::sync::atomic::AtomicUsize;
+            static SEEN: AtomicUsize = AtomicUsize::new(0);
+            static DETONATIONS_SEEN: AtomicUsize = AtomicUsize::new(0);
+
+            ::std::panic::set_hook(Box::new(|_| {
+                SEEN.fetch_add(1, Ordering::SeqCst);
+                if is_detonating() {
+                    DETONATIONS_SEEN.fetch_add(1, Ordering::SeqCst);
+                }
+            }));
+            let _ = ::std::panic::catch_unwind(|| drop(DropBomb::new("Kaboom")));
+            let _ = ::std::panic::catch_unwind(|| drop(AtomicDropBomb::new("Kaboom")));
+            let _ = ::std::panic::catch_unwind(|| panic!("unrelated panic"));
+            assert!(!is_detonating());
+            let _ = ::std::panic::take_hook();
+            assert_eq!(SEEN.load(Ordering::SeqCst), 3);
+            assert_eq!(DETONATIONS_SEEN.load(Ordering::SeqCst), 2);
+            return;
+        }
+        let output = run_child("tests::panic_hook_detects_detonations", &[]);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    fn context_round_trip() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.take_context::<u32>(), None);
+        b.set_context(String::from("handle"));
+        assert_eq!(b.take_context::<u32>(), None);
+        assert_eq!(b.take_context::<String>(), Some(String::from("handle")));
+        assert_eq!(b.take_context::<String>(), None);
+        b.set_context(1u32);
+        b.set_context(92u32);
+        assert_eq!(b.take_context::<u32>(), Some(92));
+        b.defuse();
+    }
+
+    #[test]
+    fn scope_guard_commit_defuses() {
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        let mut guard = b.scope(vec![1, 2]);
+        guard.push(3);
+        assert_eq!(guard.len(), 3);
+        let value = guard.commit();
+        assert_eq!(value, [1, 2, 3]);
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn scope_guard_dropped_leaves_bomb_armed() {
+        let mut b = DropBomb::new("Kaboom");
+        drop(b.scope(92));
+        assert!(!b.is_defused());
+        b.defuse();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn reset_all_globals_restores_defaults() {
+        if is_child() {
+            set_enabled(false);
+            set_default_action(DetonationAction::Defer);
+            set_detonation_writer(Box::new(io::sink()));
+            let from_env = env::var_os("DROP_BOMB_RUNTIME_CHECKS").is_some();
+            set_runtime_checks(!from_env);
+            let mut live = DropBomb::new("Live kaboom");
+            let _ = panic_message(|| {
+                let mut b = DropBomb::new("Kaboom");
+                b.set_action(DetonationAction::Panic);
+                set_enabled(true);
+                drop(b);
+            });
+            drop(DropBomb::new("Deferred kaboom"));
+            assert_eq!(detonation_count(), 2);
+
+            reset_all_globals();
+            assert!(global::enabled());
+            let mut b = DropBomb::new("Kaboom");
+            assert_eq!(b.action(), DetonationAction::Panic);
+            b.defuse();
+            drop(b);
+            let mut b = RuntimeBomb::new("Kaboom");
+            assert_eq!(b.is_real(), from_env);
+            b.defuse();
+            drop(b);
+            assert_eq!(detonation_count(), 0);
+            #[cfg(feature = "metrics")]
+            assert_eq!(detonation_count_for("Kaboom"), 0);
+            // No deferred detonations left to abort on.
+            flush_detonations();
+            assert_balanced_fails();
+            live.defuse();
+            drop(live);
+            assert_balanced();
+            mem::forget(DropBomb::new("Forgotten kaboom"));
+            assert_balanced_fails();
+            return;
+        }
+        for envs in [&[][..], &[("DROP_BOMB_RUNTIME_CHECKS", "1")][..]] {
+            let output = run_child("tests::reset_all_globals_restores_defaults", envs);
+            assert!(output.status.success());
+        }
+    }
+
+    #[test]
+    fn defuse_on_ok_mut_leaves_result_alone() {
+        let mut b = DropBomb::new("Kaboom");
+        let mut res: Result<u32, &str> = Err("oops");
+        b.defuse_on_ok_mut(&mut res);
+        assert!(!b.is_defused());
+        assert_eq!(res, Err("oops"));
+        res = Ok(92);
+        b.defuse_on_ok_mut(&mut res);
+        assert!(b.is_defused());
+        assert_eq!(res, Ok(92));
+    }
+
+    #[test]
+    fn new_at_reports_the_location() {
+        #[track_caller]
+        fn checked_new() -> DropBomb {
+            DropBomb::new_at("Kaboom", Location::caller())
+        }
+        let line = line!() + 1;
+        let b = checked_new();
+        let expected = format!("Kaboom (bomb created at {}:{}:", file!(), line);
+        assert!(b.detonation_message().starts_with(&expected));
+        let msg = panic_message(|| drop(b));
+        assert!(msg.starts_with(&expected));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn deferred_detonations_are_reported_at_thread_exit() {
+        if is_child() {
+            ::std::thread::spawn(|| {
+                let mut b = DropBomb::new("Thread kaboom");
+                b.set_action(DetonationAction::Defer);
+                drop(b);
+            })
+            .join()
+            .unwrap();
+            assert!(drain_pending_detonations().is_empty());
+            return;
+        }
+        let output = run_child(
+            "tests::deferred_detonations_are_reported_at_thread_exit",
+            &[],
+        );
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Thread kaboom\n"));
+    }
+
+    #[test]
+    fn drain_pending_detonations_empties_the_list() {
+        let mut a = DropBomb::new("First kaboom");
+        let mut b = DropBomb::new("Second kaboom");
+        a.set_action(DetonationAction::Defer);
+        b.set_action(DetonationAction::Defer);
+        drop((a, b));
+        assert_eq!(
+            drain_pending_detonations(),
+            ["First kaboom", "Second kaboom"]
+        );
+        assert!(drain_pending_detonations().is_empty());
+        flush_detonations();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn is_really_armed_ignores_the_global_switch() {
+        if is_child() {
+            let mut b = DropBomb::new("Kaboom");
+            assert!(b.is_armed() && b.is_really_armed());
+            set_enabled(false);
+            assert!(!b.is_armed());
+            assert!(b.is_really_armed());
+            set_enabled(true);
+            assert!(b.is_armed());
+            b.defuse();
+            assert!(!b.is_armed() && !b.is_really_armed());
+
+            let mut d = DebugDropBomb::new("Kaboom");
+            assert_eq!(d.is_really_armed(), cfg!(debug_assertions));
+            set_enabled(false);
+            assert!(!d.is_armed());
+            assert_eq!(d.is_really_armed(), cfg!(debug_assertions));
+            d.defuse();
+            return;
+        }
+        let output = run_child("tests::is_really_armed_ignores_the_global_switch", &[]);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    fn with_help_formats_a_diagnostic() {
+        let line = line!() + 1;
+        let b = DropBomb::with_help(
+            "BufWriter dropped without close",
+            "call `.close()` to handle flush errors",
+        );
+        let expected = format!(
+            "note: BufWriter dropped without close\n  --> {}:{}:17\nhelp: call `.close()` to handle flush errors",
+            file!(),
+            line
+        );
+        assert_eq!(b.detonation_message(), expected);
+        assert_eq!(panic_message(|| drop(b)), expected);
+
+        let mut b = DropBomb::new("Kaboom");
+        b.set_help("defuse it");
+        assert_eq!(b.detonation_message(), "note: Kaboom\nhelp: defuse it");
+        b.defuse();
+    }
+
+    #[test]
+    fn scoped_counters_are_per_thread() {
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                ::std::thread::spawn(move || {
+                    let counter = scoped_counter();
+                    for _ in 0..i {
+                        let _ = panic_message(|| drop(DropBomb::new("Kaboom")));
+                    }
+                    counter.finish()
+                })
+            })
+            .collect();
+        let counts: Vec<usize> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(counts, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn scoped_counters_nest() {
+        let outer = scoped_counter();
+        let _ = panic_message(|| drop(DropBomb::new("Kaboom")));
+        {
+            let inner = scoped_counter();
+            let _ = panic_message(|| drop(DropBomb::new("Kaboom")));
+            assert_eq!(inner.count(), 1);
+        }
+        assert_eq!(outer.count(), 2);
+    }
+
+    #[test]
+    fn expect_armed_passes_when_armed() {
+        let mut b = DropBomb::new("Kaboom");
+        b.expect_armed("defused too early");
+        assert!(!b.is_defused());
+        b.defuse();
+    }
+
+    #[test]
+    #[should_panic(expected = "defused too early")]
+    fn expect_armed_panics_when_defused() {
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        b.expect_armed("defused too early");
+    }
+
+    #[test]
+    fn expect_armed_if_debug() {
+        let mut b = DebugDropBomb::new("Kaboom");
+        #[cfg(debug_assertions)]
+        b.expect_armed("defused too early");
+        #[cfg(not(debug_assertions))]
+        assert_eq!(
+            panic_message(|| b.expect_armed("defused too early")),
+            "defused too early"
+        );
+        b.defuse();
+    }
+
+    #[test]
+    fn set_message_from_formats_eagerly() {
+        struct Conn(u32);
+        impl fmt::Display for Conn {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "connection #{} was not closed", self.0)
+            }
+        }
+        let mut b = DropBomb::new("Kaboom");
+        {
+            let conn = Conn(92);
+            b.set_message_from(&conn);
+        }
+        assert_eq!(b.message(), "connection #92 was not closed");
+        assert_eq!(panic_message(|| drop(b)), "connection #92 was not closed");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn is_enabled_reflects_set_enabled() {
+        if is_child() {
+            assert!(is_enabled());
+            set_enabled(false);
+            assert!(!is_enabled());
+            set_enabled(true);
+            assert!(is_enabled());
+            return;
+        }
+        let output = run_child("tests::is_enabled_reflects_set_enabled", &[]);
+        assert!(output.status.success());
+        let output = run_child(
+            "tests::is_enabled_reflects_set_enabled",
+            &[("DROP_BOMB_DISABLE", "1")],
+        );
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    fn map_action_escalates() {
+        let mut b = DropBomb::new("Kaboom");
+        b.set_action(DetonationAction::Panic);
+        b.map_action(|it| match it {
+            DetonationAction::Panic => DetonationAction::Abort,
+            other => other,
+        });
+        assert_eq!(b.action(), DetonationAction::Abort);
+        b.defuse();
+    }
+
+    #[test]
+    fn close_checked_buf_writer() {
+        use std::io::{self, BufWriter, Write};
+
+        struct FailingWriter;
+        impl Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        struct CheckedBufWriter<W: Write> {
+            inner: BufWriter<W>,
+            bomb: DropBomb,
+        }
+        impl<W: Write> CheckedBufWriter<W> {
+            fn new(inner: W) -> CheckedBufWriter<W> {
+                let bomb = DropBomb::new("CheckedBufWriter must be explicitly closed");
+                CheckedBufWriter {
+                    inner: BufWriter::new(inner),
+                    bomb,
+                }
+            }
+        }
+        impl<W: Write> CloseChecked for CheckedBufWriter<W> {
+            type Ok = ();
+            type Err = io::Error;
+            fn close(mut self) -> io::Result<()> {
+                let inner = &mut self.inner;
+                close_with(&mut self.bomb, || inner.flush())
+            }
+        }
+
+        let mut w = CheckedBufWriter::new(Vec::new());
+        w.inner.write_all(b"hello").unwrap();
+        assert!(w.close().is_ok());
+
+        let mut w = CheckedBufWriter::new(FailingWriter);
+        w.inner.write_all(b"hello").unwrap();
+        assert_eq!(w.close().unwrap_err().to_string(), "disk full");
+    }
+
+    #[test]
+    fn defuse_nth_defuses_one() {
+        let mut bombs = [
+            DropBomb::new("Kaboom"),
+            DropBomb::new("Kaboom"),
+            DropBomb::new("Kaboom"),
+        ];
+        assert_eq!(defuse_nth(&mut bombs, 1), Ok(()));
+        assert_eq!(
+            bombs.iter().map(DropBomb::is_defused).collect::<Vec<_>>(),
+            [false, true, false]
+        );
+        let err = defuse_nth(&mut bombs, 3).unwrap_err();
+        assert_eq!(err, IndexError { index: 3, len: 3 });
+        assert_eq!(
+            err.to_string(),
+            "drop bomb index 3 is out of bounds for 3 bomb(s)"
+        );
+        defuse_array(&mut bombs);
+    }
+
+    #[test]
+    #[cfg(feature = "intern")]
+    fn interned_messages_are_shared() {
+        let mut a = DropBomb::new_interned(format!("Kaboom #{}", 92));
+        let mut b = DropBomb::new_interned(format!("Kaboom #{}", 92));
+        let mut c = DropBomb::new_interned(format!("Kaboom #{}", 62));
+        assert_eq!(a.message(), "Kaboom #92");
+        assert_eq!(a.message().as_ptr(), b.message().as_ptr());
+        assert_ne!(a.message().as_ptr(), c.message().as_ptr());
+        a.defuse();
+        b.defuse();
+        c.defuse();
+    }
+
+    #[test]
+    fn catch_defuses_on_normal_return() {
+        let mut b = DropBomb::new("Kaboom");
+        assert_eq!(b.catch(|| 92).unwrap(), 92);
+        assert!(b.is_defused());
+    }
+
+    #[test]
+    fn catch_leaves_bomb_armed_on_panic() {
+        let mut b = DropBomb::new("Kaboom");
+        let res = b.catch(|| -> u32 { panic!("oops") });
+        assert_eq!(*res.unwrap_err().downcast::<&str>().unwrap(), "oops");
+        assert!(!b.is_defused());
+        b.defuse();
+    }
+
+    #[test]
+    fn message_ptr_eq_detects_shared_messages() {
+        let msg: &'static str = "Kaboom";
+        let mut a = DropBomb::new(msg);
+        let mut b = DropBomb::new(msg);
+        let mut c = DropBomb::new(String::from("Kaboom"));
+        let mut d = DropBomb::new(String::from("Kaboom"));
+        assert!(a.message_ptr_eq(&b));
+        assert!(!a.message_ptr_eq(&c));
+        assert!(!c.message_ptr_eq(&d));
+        assert!(c.message_ptr_eq(&c));
+        let mut g = DropBomb::new(String::new());
+        let mut h = DropBomb::new(String::new());
+        assert!(!g.message_ptr_eq(&h));
+        assert!(g.message_ptr_eq(&g));
+        #[cfg(feature = "intern")]
+        {
+            let mut e = DropBomb::new_interned(String::from("Interned kaboom"));
+            let mut f = DropBomb::new_interned(String::from("Interned kaboom"));
+            assert!(e.message_ptr_eq(&f));
+            e.defuse();
+            f.defuse();
+        }
+        for it in [&mut a, &mut b, &mut c, &mut d, &mut g, &mut h] {
+            it.defuse();
+        }
+    }
+
+    #[test]
+    fn severity_selects_action() {
+        let b = DropBomb::with_severity("Warn kaboom", Severity::Warn);
+        assert_eq!(b.action(), DetonationAction::Warn);
+        drop(b);
+        let b = DropBomb::with_severity("Error kaboom", Severity::Error);
+        assert_eq!(b.action(), DetonationAction::Panic);
+        assert_eq!(panic_message(|| drop(b)), "Error kaboom");
+        let mut b = DropBomb::with_severity("Kaboom", Severity::Fatal);
+        b.set_action(DetonationAction::Warn);
+        drop(b);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn fatal_severity_aborts() {
+        if is_child() {
+            let _b = DropBomb::with_severity("Fatal kaboom", Severity::Fatal);
+            return;
+        }
+        let output = run_child("tests::fatal_severity_aborts", &[]);
+        assert!(!output.status.success());
+        assert_ne!(output.status.code(), Some(101));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Fatal kaboom"));
+    }
+
+    #[test]
+    fn clear_config_restores_plain_behavior() {
+        let mut b = DropBomb::with_help("Kaboom", "defuse it");
+        b.set_action(DetonationAction::Warn);
+        b.set_detonate_during_panic(true);
+        b.set_context(92u32);
+        b.set_on_defuse(|| panic!("hook should have been cleared"));
+        b.clear_config();
+        assert_eq!(b.action(), DetonationAction::Panic);
+        assert_eq!(b.take_context::<u32>(), None);
+        assert_eq!(b.detonation_message(), "Kaboom");
+        assert!(!b.is_defused());
+        b.defuse();
+        b.arm();
+        assert_eq!(panic_message(|| drop(b)), "Kaboom");
+
+        let mut b = DropBomb::new("Kaboom");
+        b.seal();
+        b.defuse();
+        b.clear_config();
+        assert!(b.is_defused());
+        b.arm();
+        assert!(!b.is_defused());
+        b.defuse();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn runtime_bomb_follows_the_flag() {
+        if is_child() {
+            let from_env = env::var_os("DROP_BOMB_RUNTIME_CHECKS").is_some();
+            let mut b = RuntimeBomb::new("Kaboom");
+            assert_eq!(b.is_real(), from_env);
+            b.defuse();
+
+            set_runtime_checks(false);
+            let b = RuntimeBomb::new("Kaboom");
+            assert!(b.is_defused());
+            assert_eq!(b.message(), "");
+            drop(b);
+
+            set_runtime_checks(true);
+            let b = RuntimeBomb::new("Kaboom");
+            assert!(!b.is_defused());
+            assert_eq!(b.message(), "Kaboom");
+            assert_eq!(panic_message(|| drop(b)), "Kaboom");
+            return;
+        }
+        for envs in [&[][..], &[("DROP_BOMB_RUNTIME_CHECKS", "1")][..]] {
+            let output = run_child("tests::runtime_bomb_follows_the_flag", envs);
+            assert!(output.status.success());
+        }
+    }
+
+    #[test]
+    fn consuming_a_bomb_does_not_run_on_defuse() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let armed_with_hook = |msg: &'static str| {
+            let count = Arc::clone(&count);
+            let mut b = DropBomb::new(msg);
+            b.set_on_defuse(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+            b
+        };
+
+        let (msg, defused) = armed_with_hook("Kaboom").into_parts();
+        let mut b = DropBomb::from_parts(msg, defused);
+        b.defuse();
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        let mut d = armed_with_hook("Kaboom").into_debug();
+        d.defuse();
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        let mut merged = armed_with_hook("a").merge(armed_with_hook("b"));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        merged.defuse();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn atomic_is_armed_follows_the_global_switch() {
+        if is_child() {
+            let a = AtomicDropBomb::new("Kaboom");
+            assert!(a.is_armed() && a.is_really_armed());
+            set_enabled(false);
+            assert!(!a.is_armed());
+            assert!(a.is_really_armed());
+            set_enabled(true);
+            a.defuse();
+            assert!(!a.is_armed() && !a.is_really_armed());
+            return;
+        }
+        let output = run_child("tests::atomic_is_armed_follows_the_global_switch", &[]);
+        assert!(output.status.success());
+    }
+
+    #[test]
+    #[cfg(feature = "track-defuse")]
+    fn defuse_helpers_track_the_caller() {
+        let defused_at =
+            |line: u32| format!(r#"DropBomb(defused at {}:{}): "Kaboom""#, file!(), line);
+
+        let mut a = DropBomb::new("Kaboom");
+        let mut b = DropBomb::new("Kaboom");
+        defuse_pair(&mut a, &mut b);
+        let line = line!() - 1;
+        assert_eq!(a.to_string(), defused_at(line));
+        assert_eq!(b.to_string(), defused_at(line));
+
+        let mut bombs = [DropBomb::new("Kaboom"), DropBomb::new("Kaboom")];
+        defuse_array(&mut bombs);
+        let line = line!() - 1;
+        assert!(bombs.iter().all(|it| it.to_string() == defused_at(line)));
+
+        let mut bombs = vec![DropBomb::new("Kaboom")];
+        let line = line!() + 1;
+        drop(guard_all(&mut bombs));
+        assert_eq!(bombs[0].to_string(), defused_at(line));
+
+        // Bombs built already defused have no defuse site.
+        let defused = r#"DropBomb(defused): "Kaboom""#;
+        let mut template = DropBomb::new("Kaboom");
+        assert_eq!(template.clone_disarmed().to_string(), defused);
+        template.defuse();
+        assert_eq!(
+            DropBomb::from_parts("Kaboom".into(), true).to_string(),
+            defused
+        );
+        let mut debug = DebugDropBomb::new("Kaboom");
+        debug.defuse();
+        let real = debug.into_real();
+        #[cfg(debug_assertions)]
+        assert_eq!(real.to_string(), defused);
+        let mut a = DropBomb::new("Kaboom");
+        a.defuse();
+        let mut b = DropBomb::new("Kaboom");
+        b.defuse();
+        let merged = a.merge(b);
+        assert_eq!(merged.to_string(), "DropBomb(defused): \"Kaboom\\nKaboom\"");
+        drop((real, merged));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn panic_immediately_if_armed_reports_the_caller() {
+        let line = line!() + 4;
+        if is_child() {
+            let mut a = DropBomb::new("Checkpoint kaboom");
+            let mut b = DebugDropBomb::new("Checkpoint kaboom");
+            let _ = panic_message(|| a.panic_immediately_if_armed());
+            #[cfg(debug_assertions)]
+            let _ = panic_message(|| b.panic_immediately_if_armed());
+            a.defuse();
+            b.defuse();
+            return;
+        }
+        let output = run_child(
+            "tests::panic_immediately_if_armed_reports_the_caller",
+            &[("RUST_BACKTRACE", "0")],
+        );
+        assert!(output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(&format!("panicked at {}:{}:", file!(), line)));
+        #[cfg(debug_assertions)]
+        assert!(stderr.contains(&format!("panicked at {}:{}:", file!(), line + 2)));
+    }
+
+    #[test]
+    fn merge_keeps_the_stricter_action() {
+        use DetonationAction::*;
+        for &(a, b, expected) in &[
+            (Warn, Warn, Warn),
+            (Warn, Defer, Defer),
+            (Panic, Defer, Panic),
+            (Abort, Panic, Abort),
+            (Warn, Abort, Abort),
+        ] {
+            let mut x = DropBomb::new("Kaboom");
+            x.set_action(a);
+            let mut y = DropBomb::new("Kaboom");
+            y.set_action(b);
+            let mut merged = x.merge(y);
+            assert_eq!(merged.action(), expected);
+            merged.defuse();
+        }
+    }
+
+    #[test]
+    fn check_traits() {
+        fn assert_traits<T: ::std::fmt::Debug + Send + Sync>() {}
+        assert_traits::<DropBomb>();
         assert_traits::<DebugDropBomb>();
+        assert_traits::<AtomicDropBomb>();
+        assert_traits::<PhantomBomb>();
+        assert_traits::<RuntimeBomb>();
+        #[cfg(feature = "compact")]
+        assert_traits::<CodeBomb>();
+
+        fn assert_thread_safe<T: Send + Sync + ?Sized>(_: &T) {}
+        let mut boxed = new_boxed("Kaboom", false);
+        assert_thread_safe::<Box<dyn Bomb>>(&boxed);
+        boxed.defuse();
+        // The stored closures and context must not cost the bomb its
+        // thread safety.
+        let mut b = DropBomb::with_help("Kaboom", "defuse it");
+        let shared = ::std::sync::Arc::new(::std::sync::Mutex::new(Vec::<u8>::new()));
+        let on_defuse = ::std::sync::Arc::clone(&shared);
+        b.set_on_defuse(move || on_defuse.lock().unwrap().push(1));
+        b.set_context(::std::sync::Arc::clone(&shared));
+        b.set_action(DetonationAction::Defer);
+        assert_thread_safe(&b);
+        let mut b = ::std::thread::spawn(move || b).join().unwrap();
+        b.defuse();
+        assert_eq!(*shared.lock().unwrap(), [1]);
+
+        // The hook and the context only need to be `Send`.
+        let cell = ::std::cell::Cell::new(0);
+        let mut b = DropBomb::new("Kaboom");
+        b.set_on_defuse(move || cell.set(1));
+        b.set_context(::std::cell::Cell::new(92));
+        assert_thread_safe(&b);
+        b.defuse();
     }
 }
@@ -0,0 +1,64 @@
+//! Neutral names for the types in this crate, for codebases where the
+//! "bomb" metaphor doesn't fit.
+//!
+//! `LinearGuard` is `DropBomb`, `DebugLinearGuard` is `DebugDropBomb`,
+//! and `Commit::commit` is `defuse`. These are plain aliases: nothing
+//! here changes behavior.
+use super::{DebugDropBomb, DropBomb};
+
+pub type LinearGuard = DropBomb;
+pub type DebugLinearGuard = DebugDropBomb;
+
+pub trait Commit {
+    /// Same as `defuse`.
+    fn commit(&mut self);
+    /// Same as `is_defused`.
+    fn is_committed(&self) -> bool;
+}
+
+impl Commit for DropBomb {
+    fn commit(&mut self) {
+        self.defuse()
+    }
+    fn is_committed(&self) -> bool {
+        self.is_defused()
+    }
+}
+
+impl Commit for DebugDropBomb {
+    fn commit(&mut self) {
+        self.defuse()
+    }
+    fn is_committed(&self) -> bool {
+        self.is_defused()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "Kaboom")]
+    fn uncommitted_guard_bombs() {
+        let _g = LinearGuard::new("Kaboom");
+    }
+
+    #[test]
+    fn committed_guard_is_safe() {
+        let mut g = LinearGuard::new("Kaboom");
+        assert!(!g.is_committed());
+        g.commit();
+        assert!(g.is_committed());
+        assert!(g.is_defused());
+    }
+
+    #[test]
+    fn committed_guard_is_safe_if_debug() {
+        let mut g = DebugLinearGuard::new("Kaboom");
+        #[cfg(debug_assertions)]
+        assert!(!g.is_committed());
+        g.commit();
+        assert!(g.is_committed());
+    }
+}
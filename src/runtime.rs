@@ -0,0 +1,56 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use global;
+use RealBomb;
+
+/// A bomb which decides at runtime, when it is created, whether it is a
+/// real `DropBomb` or a no-op like a release-build `DebugDropBomb`.
+///
+/// Bombs are real if `set_runtime_checks(true)` was called, or if the
+/// process was started with `DROP_BOMB_RUNTIME_CHECKS=1`. This gives
+/// release builds deploy-time control over checking. When checks are
+/// off, a `RuntimeBomb` is always defused, has an empty message, and
+/// its drop is just a branch.
+#[must_use]
+#[clippy::has_significant_drop]
+pub struct RuntimeBomb(Option<RealBomb>);
+
+impl RuntimeBomb {
+    pub fn new(msg: impl Into<Cow<'static, str>>) -> RuntimeBomb {
+        if global::runtime_checks() {
+            RuntimeBomb(Some(RealBomb::new(msg.into())))
+        } else {
+            RuntimeBomb(None)
+        }
+    }
+    /// Whether this bomb is a real one.
+    pub fn is_real(&self) -> bool {
+        self.0.is_some()
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn defuse(&mut self) {
+        self.set_defused(true)
+    }
+    #[cfg_attr(feature = "track-defuse", track_caller)]
+    pub fn set_defused(&mut self, defused: bool) {
+        if let Some(ref mut bomb) = self.0 {
+            bomb.set_defused(defused)
+        }
+    }
+    pub fn is_defused(&self) -> bool {
+        self.0.as_ref().is_none_or(RealBomb::is_defused)
+    }
+    pub fn message(&self) -> &str {
+        self.0.as_ref().map_or("", RealBomb::message)
+    }
+}
+
+impl fmt::Debug for RuntimeBomb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RuntimeBomb")
+            .field("message", &self.message())
+            .field("defused", &self.is_defused())
+            .finish()
+    }
+}
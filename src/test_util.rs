@@ -0,0 +1,86 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+use global;
+
+/// Runs `f`, returning the panic message if it panicked, for example
+/// because a bomb detonated, and `None` otherwise.
+///
+/// The default panic output is suppressed while `f` runs.
+pub fn capture_detonation(f: impl FnOnce()) -> Option<String> {
+    // The panic hook is process-wide: serialize swapping it, so that
+    // concurrent callers restore the right hook. Nested calls on the
+    // same thread already hold the lock and have the hook swapped.
+    let outermost = CAPTURE_DEPTH.with(|it| {
+        it.set(it.get() + 1);
+        it.get() == 1
+    });
+    let mut guard = None;
+    let mut prev_hook = None;
+    if outermost {
+        guard = Some(
+            global::PANIC_HOOK_LOCK
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()),
+        );
+        prev_hook = Some(panic::take_hook());
+        panic::set_hook(Box::new(|_| ()));
+    }
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    CAPTURE_DEPTH.with(|it| it.set(it.get() - 1));
+    if let Some(prev_hook) = prev_hook {
+        panic::set_hook(prev_hook);
+    }
+    drop(guard);
+    res.err().map(payload_message)
+}
+
+thread_local! {
+    static CAPTURE_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+fn payload_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast_ref::<&str>() {
+            Some(msg) => msg.to_string(),
+            None => "Box<dyn Any>".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DropBomb;
+
+    #[test]
+    fn captures_detonation() {
+        let msg = capture_detonation(|| {
+            let _b = DropBomb::new("Kaboom");
+        });
+        assert_eq!(msg.as_deref(), Some("Kaboom"));
+    }
+
+    #[test]
+    fn captures_nothing_without_detonation() {
+        let msg = capture_detonation(|| {
+            let mut b = DropBomb::new("Kaboom");
+            b.defuse();
+        });
+        assert_eq!(msg, None);
+    }
+
+    #[test]
+    fn nested_captures_do_not_deadlock() {
+        let outer = capture_detonation(|| {
+            let inner = capture_detonation(|| {
+                let _b = DropBomb::new("Inner kaboom");
+            });
+            assert_eq!(inner.as_deref(), Some("Inner kaboom"));
+            let _b = DropBomb::new("Outer kaboom");
+        });
+        assert_eq!(outer.as_deref(), Some("Outer kaboom"));
+    }
+}
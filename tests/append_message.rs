@@ -0,0 +1,60 @@
+//! Checks allocations made by `append_message`. This lives in its own
+//! test binary because it installs a counting global allocator.
+extern crate drop_bomb;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use drop_bomb::DropBomb;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn count_allocations(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.store(0, Ordering::Relaxed);
+    COUNTING.with(|it| it.set(true));
+    f();
+    COUNTING.with(|it| it.set(false));
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+#[test]
+#[cfg_attr(feature = "debug_registry", ignore)] // the registry copies the message
+fn append_to_static_message_allocates_once() {
+    let mut b = DropBomb::new("Kaboom");
+    let n = count_allocations(|| {
+        b.append_message(": while closing");
+        b.append_message(": ");
+        b.append_message("file.txt");
+    });
+    assert_eq!(n, 1);
+    assert_eq!(b.message(), "Kaboom: while closing: file.txt");
+    b.defuse();
+}